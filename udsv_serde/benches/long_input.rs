@@ -0,0 +1,29 @@
+// Deserializing ~100KB records dense with escaped delimiters, which used to
+// take quadratic time to find the end of each field.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Deserialize;
+use udsv::record_from_str;
+
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Test {
+    a: String,
+    b: String,
+}
+
+fn long_input(c: &mut Criterion) {
+    let field = r"a\:\,\=\\".repeat(5_000);
+    let record = format!("{}:{}", field, field);
+    c.bench_function("escaped fields", |b| {
+        b.iter(|| record_from_str::<Test>(black_box(&record)).unwrap())
+    });
+
+    let record = vec![r"a\:b\,c"; 12_500].join(",");
+    c.bench_function("escaped list", |b| {
+        b.iter(|| record_from_str::<Vec<String>>(black_box(&record)).unwrap())
+    });
+}
+
+criterion_group!(benches, long_input);
+criterion_main!(benches);
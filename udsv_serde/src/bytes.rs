@@ -0,0 +1,59 @@
+// Bytes have no textual representation of their own, so they are encoded as
+// lowercase hex, or base64 when that feature is enabled. Without either feature
+// bytes are unsupported.
+
+use crate::err::{Error, Result};
+
+#[cfg(feature = "base64")]
+pub(crate) fn encode(v: &[u8]) -> Result<String> {
+    use base64::Engine;
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(v))
+}
+
+#[cfg(feature = "base64")]
+pub(crate) fn decode(s: &str) -> Result<Vec<u8>> {
+    use base64::Engine;
+
+    base64::engine::general_purpose::STANDARD
+        .decode(s)
+        .map_err(|_| Error::ExpectedBytes)
+}
+
+#[cfg(all(feature = "hex", not(feature = "base64")))]
+pub(crate) fn encode(v: &[u8]) -> Result<String> {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut s = String::with_capacity(v.len() * 2);
+    for byte in v {
+        s.push(DIGITS[(byte >> 4) as usize] as char);
+        s.push(DIGITS[(byte & 0xf) as usize] as char);
+    }
+    Ok(s)
+}
+
+#[cfg(all(feature = "hex", not(feature = "base64")))]
+pub(crate) fn decode(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return Err(Error::ExpectedBytes);
+    }
+
+    let digit = |byte: u8| char::from(byte).to_digit(16);
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| match (digit(pair[0]), digit(pair[1])) {
+            (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+            _ => Err(Error::ExpectedBytes),
+        })
+        .collect()
+}
+
+#[cfg(not(any(feature = "hex", feature = "base64")))]
+pub(crate) fn encode(_v: &[u8]) -> Result<String> {
+    Err(Error::BytesUnsupported)
+}
+
+#[cfg(not(any(feature = "hex", feature = "base64")))]
+pub(crate) fn decode(_s: &str) -> Result<Vec<u8>> {
+    Err(Error::BytesUnsupported)
+}
@@ -0,0 +1,232 @@
+use crate::err::{Error, Result};
+
+// Characters that can't be used as a delimiter as they already have a meaning
+// after the escape character, or separate records.
+const RESERVED: [char; 6] = ['\\', '\n', 'n', 'r', 't', 'x'];
+
+/// Options for reading and writing records. New options may be added, so start
+/// from `Config::default()` and set the fields needed.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct Config {
+    /// Separates the fields of a record, `:` by default.
+    pub field_delimiter: char,
+    /// Separates the items of a list or map, `,` by default.
+    pub item_delimiter: char,
+    /// Separates a map key from its value, `=` by default.
+    pub key_value_delimiter: char,
+    /// Escape ASCII control characters other than newline, carriage return,
+    /// and tab as `\xNN` when serializing, off by default.
+    pub escape_control: bool,
+    /// Error on unknown escape sequences when deserializing rather than
+    /// keeping them as is, off by default.
+    pub strict_escapes: bool,
+    /// Read an empty field as `None` where the type is only known at runtime,
+    /// e.g. in a flattened struct, off by default.
+    pub empty_as_none: bool,
+    /// Ignore whitespace at the end of a record when deserializing, such as
+    /// the newline left by reading a line, off by default.
+    pub allow_trailing_whitespace: bool,
+    /// Write booleans as `1` and `0`, and read either form, off by default.
+    pub bool_as_int: bool,
+    /// Written after every delimiter when serializing, and skipped after them
+    /// when deserializing, e.g. `" "` for easier reading. Empty by default.
+    pub spacing: String,
+    /// How deeply structs, tuples, lists, maps, and enums may be nested when
+    /// deserializing, guarding against a stack overflow on untrusted input.
+    /// 128 by default.
+    pub max_depth: usize,
+    /// Write a delimiter after the last item of a non-empty list, so a list of
+    /// one item doesn't look like a scalar. It is optional when deserializing,
+    /// unless `strict_trailing_delimiter` is set. Off by default.
+    pub trailing_delimiter: bool,
+    /// Require the delimiter after the last item of a list when deserializing
+    /// with `trailing_delimiter`. Off by default.
+    pub strict_trailing_delimiter: bool,
+    /// Starts an escape sequence, `\` by default.
+    pub escape_char: char,
+    /// Break string values with an escaped newline so lines are at most this
+    /// many characters when serializing, where possible. Off by default.
+    pub wrap_width: Option<usize>,
+    /// Reject integers with leading zeros, e.g. `007`, when deserializing,
+    /// off by default.
+    pub strict_numbers: bool,
+    /// Neither escape values when serializing nor unescape them when
+    /// deserializing, which saves the work for trusted data. A value
+    /// containing a delimiter, newline, or the escape character is then
+    /// written as is, and can't be read back correctly. Off by default.
+    pub raw: bool,
+    /// Written for `None`, so it can be told apart from an empty value such as
+    /// `Some("")`, and read back as `None`. It must be the escape character
+    /// followed by characters that never follow it otherwise, e.g. `\0`. Off by
+    /// default.
+    pub null_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            field_delimiter: ':',
+            item_delimiter: ',',
+            key_value_delimiter: '=',
+            escape_control: false,
+            strict_escapes: false,
+            empty_as_none: false,
+            allow_trailing_whitespace: false,
+            bool_as_int: false,
+            spacing: String::new(),
+            max_depth: 128,
+            trailing_delimiter: false,
+            strict_trailing_delimiter: false,
+            escape_char: '\\',
+            wrap_width: None,
+            strict_numbers: false,
+            raw: false,
+            null_token: None,
+        }
+    }
+}
+
+impl Config {
+    pub(crate) fn validate(&self) -> Result<()> {
+        let delimiters = [
+            self.field_delimiter,
+            self.item_delimiter,
+            self.key_value_delimiter,
+        ];
+
+        if delimiters.iter().any(|ch| RESERVED.contains(ch)) {
+            return Err(Error::InvalidConfig(
+                "delimiters may not be a backslash, newline, or escape sequence letter",
+            ));
+        }
+
+        // The escape character may be a backslash, but not a delimiter, newline,
+        // or letter used in an escape sequence
+        if RESERVED[1..].contains(&self.escape_char) || delimiters.contains(&self.escape_char) {
+            return Err(Error::InvalidConfig(
+                "escape_char may not be a delimiter, newline, or escape sequence letter",
+            ));
+        }
+
+        if self.spacing.chars().any(|ch| {
+            ch == '\\' || ch == self.escape_char || ch == '\n' || delimiters.contains(&ch)
+        }) {
+            return Err(Error::InvalidConfig(
+                "spacing may not contain a delimiter, escape character, or newline",
+            ));
+        }
+
+        // An escaped value never starts with the escape character followed by
+        // anything but a delimiter or escape sequence, so can't look like this
+        if let Some(token) = &self.null_token {
+            let mut chars = token.chars();
+            let valid = chars.next() == Some(self.escape_char)
+                && chars
+                    .next()
+                    .is_some_and(|ch| !RESERVED.contains(&ch) && ch != self.escape_char)
+                && token
+                    .chars()
+                    .skip(1)
+                    .all(|ch| ch != self.escape_char && ch != '\n' && !delimiters.contains(&ch));
+            if !valid {
+                return Err(Error::InvalidConfig(
+                    "null_token must be the escape character followed by characters that \
+                     never follow it otherwise",
+                ));
+            }
+        }
+
+        // Wrapping relies on escaped newlines
+        if self.raw && self.wrap_width.is_some() {
+            return Err(Error::InvalidConfig(
+                "raw can't be combined with wrap_width",
+            ));
+        }
+
+        if self.field_delimiter == self.item_delimiter
+            || self.field_delimiter == self.key_value_delimiter
+            || self.item_delimiter == self.key_value_delimiter
+        {
+            return Err(Error::InvalidConfig("delimiters must be distinct"));
+        }
+
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+
+    use crate::{Config, Error};
+
+    #[test]
+    fn test_validate() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = Config {
+            field_delimiter: '|',
+            item_delimiter: ';',
+            key_value_delimiter: ':',
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            item_delimiter: ':',
+            ..Config::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(Error::InvalidConfig("delimiters must be distinct"))
+        ));
+
+        let config = Config {
+            key_value_delimiter: '\\',
+            ..Config::default()
+        };
+        assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+
+        let config = Config {
+            escape_char: '~',
+            ..Config::default()
+        };
+        assert!(config.validate().is_ok());
+
+        for null_token in ["\\0", "\\0-"] {
+            let config = Config {
+                null_token: Some(null_token.to_owned()),
+                ..Config::default()
+            };
+            assert!(config.validate().is_ok());
+        }
+        for null_token in ["", "null", "\\", "\\n", "\\:", "\\0:", "\\0\\"] {
+            let config = Config {
+                null_token: Some(null_token.to_owned()),
+                ..Config::default()
+            };
+            assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+        }
+
+        for escape_char in [':', 'n', '\n'] {
+            let config = Config {
+                escape_char,
+                ..Config::default()
+            };
+            assert!(matches!(config.validate(), Err(Error::InvalidConfig(_))));
+        }
+
+        // The error says which option is wrong
+        let config = Config {
+            raw: true,
+            wrap_width: Some(80),
+            ..Config::default()
+        };
+        assert_eq!(
+            "Invalid config: raw can't be combined with wrap_width",
+            config.validate().unwrap_err().to_string()
+        );
+    }
+}
@@ -1,39 +1,282 @@
-use std::ops::{AddAssign, MulAssign, Neg};
+use std::borrow::Cow;
+use std::io;
+use std::str::FromStr;
 
+use num_traits::{CheckedAdd, CheckedMul};
 use serde::de::{
-    self, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
     Visitor,
 };
 use serde::Deserialize;
 
+use crate::bytes;
+use crate::config::Config;
 use crate::err::{Error, Result};
 
 pub struct Deserializer<'de> {
     input: &'de str,
+    // The whole input, to find the position of errors
+    original: &'de str,
     in_seq: bool,
     in_map: bool,
+    // Set while `deserialize_any` reads the rest of a record as a sequence
+    in_fields: bool,
+    // Set while reading the value of a `Some`, until anything else that holds
+    // values, e.g. a list or struct, starts
+    in_some: bool,
+    // How many lists, maps, tuples, and structs the next value is inside
+    depth: usize,
+    config: Config,
 }
 
 impl<'de> Deserializer<'de> {
-    fn from_str(input: &'de str) -> Self {
+    /// Create a deserializer that borrows from `input`, for driving
+    /// deserialization manually with `Deserialize::deserialize(&mut de)`.
+    ///
+    /// Unlike `record_from_str`, this does not check that all of the input was
+    /// consumed, use `end` to check for trailing characters.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        Deserializer::with_config(input, Config::default())
+    }
+
+    /// Configure a deserializer, e.g. to read a value as if it were already
+    /// inside a list or map. This suits formats embedding UDSV, where a value
+    /// taken from a larger structure should stop at the same delimiters as
+    /// it would in a whole record.
+    pub fn builder() -> DeserializerBuilder {
+        DeserializerBuilder::default()
+    }
+
+    fn with_config(input: &'de str, config: Config) -> Self {
         Deserializer {
             input,
+            original: input,
             in_seq: false,
             in_map: false,
+            in_fields: false,
+            in_some: false,
+            depth: 0,
+            config,
+        }
+    }
+
+    /// Whether all of the input has been consumed.
+    pub fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+
+    /// The 1-based line and column, in characters, of the next character to
+    /// be read.
+    pub fn position(&self) -> (usize, usize) {
+        let consumed = &self.original[..self.original.len() - self.input.len()];
+        let line = consumed.matches('\n').count() + 1;
+        let line_start = consumed.rfind('\n').map(|idx| idx + 1).unwrap_or(0);
+        let column = consumed[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Check that all of the input has been consumed, as `record_from_str`
+    /// does.
+    pub fn end(self) -> Result<()> {
+        if self.input.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::TrailingCharacters)
         }
     }
 }
 
+/// Built with `Deserializer::builder`.
+#[derive(Clone, Debug, Default)]
+pub struct DeserializerBuilder {
+    config: Config,
+    in_seq: bool,
+    in_map: bool,
+}
+
+impl DeserializerBuilder {
+    /// Use `config` rather than `Config::default()`.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Read values as items of a list, ending at an item delimiter.
+    pub fn in_seq(mut self, in_seq: bool) -> Self {
+        self.in_seq = in_seq;
+        self
+    }
+
+    /// Read values as keys or values of a map, ending at an item or key value
+    /// delimiter.
+    pub fn in_map(mut self, in_map: bool) -> Self {
+        self.in_map = in_map;
+        self
+    }
+
+    /// Create the deserializer, checking the config is valid.
+    pub fn build(self, input: &str) -> Result<Deserializer<'_>> {
+        self.config.validate()?;
+        Ok(Deserializer {
+            in_seq: self.in_seq,
+            in_map: self.in_map,
+            ..Deserializer::with_config(input, self.config)
+        })
+    }
+}
+
 pub fn record_from_str<'a, T>(s: &'a str) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_str(s);
+    record_from_str_with_config(s, Config::default())
+}
+
+pub fn record_from_str_with_config<'a, T>(s: &'a str, config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    config.validate()?;
+    let s = trim_record(s, &config);
+    let mut deserializer = Deserializer::with_config(s, config);
     let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.is_empty() {
-        Ok(t)
+    deserializer.end()?;
+    Ok(t)
+}
+
+// Trim trailing whitespace if the config allows it.
+fn trim_record<'a>(s: &'a str, config: &Config) -> &'a str {
+    if config.allow_trailing_whitespace && config.raw {
+        s.trim_end()
+    } else if config.allow_trailing_whitespace {
+        trim_end_unescaped(s, config.escape_char)
     } else {
-        Err(Error::TrailingCharacters)
+        s
+    }
+}
+
+// Trim trailing whitespace, other than an escaped whitespace character.
+fn trim_end_unescaped(s: &str, escape_char: char) -> &str {
+    let trimmed = s.trim_end();
+    let escapes = trimmed
+        .chars()
+        .rev()
+        .take_while(|&ch| ch == escape_char)
+        .count();
+    match s[trimmed.len()..].chars().next() {
+        Some(ch) if escapes % 2 == 1 => &s[..trimmed.len() + ch.len_utf8()],
+        _ => trimmed,
+    }
+}
+
+/// Deserialize a record from UTF-8 bytes, such as a network buffer.
+pub fn record_from_slice<'a, T>(v: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    record_from_str(std::str::from_utf8(v).map_err(Error::InvalidUtf8)?)
+}
+
+/// Replace the escape sequences in a field value taken from a record by hand.
+pub fn unescape_field(s: &str) -> Result<String> {
+    Deserializer::from_str(s).unescape(s)
+}
+
+/// Read a whole record from `reader`. The record is read into a buffer that is
+/// dropped on return, so unlike `record_from_str` nothing can borrow from it.
+pub fn record_from_reader<R, T>(mut reader: R) -> Result<T>
+where
+    R: io::Read,
+    T: DeserializeOwned,
+{
+    let mut s = String::new();
+    reader.read_to_string(&mut s)?;
+    record_from_str(&s)
+}
+
+pub fn records_from_str<'a, T>(s: &'a str) -> impl Iterator<Item = Result<T>> + 'a
+where
+    T: Deserialize<'a> + 'a,
+{
+    records_from_str_with_config(s, Config::default()).expect("the default config is valid")
+}
+
+/// Split `s` into records like `records_from_str`, reading each with `config`.
+/// Newlines escaped with `Config::escape_char` continue a record.
+pub fn records_from_str_with_config<'a, T>(
+    s: &'a str,
+    config: Config,
+) -> Result<impl Iterator<Item = Result<T>> + 'a>
+where
+    T: Deserialize<'a> + 'a,
+{
+    config.validate()?;
+    let records = Records {
+        input: s,
+        line: 1,
+        escape_char: (!config.raw).then_some(config.escape_char),
+    };
+    Ok(records.map(move |(line, record)| record_from_line(record, line, config.clone())))
+}
+
+// Errors give their position in the whole file, rather than in the record.
+fn record_from_line<'a, T>(s: &'a str, line: usize, config: Config) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let s = trim_record(s, &config);
+    let mut deserializer = Deserializer::with_config(s, config);
+    let result = T::deserialize(&mut deserializer);
+    // `end` consumes the deserializer, so find where it stopped first
+    let (record_line, column) = deserializer.position();
+    result
+        .and_then(|t| deserializer.end().map(|()| t))
+        .map_err(|error| Error::Position {
+            line: line + record_line - 1,
+            column,
+            error: Box::new(error),
+        })
+}
+
+// Splits a file into records on newlines, skipping escaped newlines as these
+// are record continuations. A single trailing newline is allowed.
+struct Records<'a> {
+    input: &'a str,
+    // The line the next record starts on
+    line: usize,
+    // As for `Deserializer::is_escape`, nothing is escaped in raw mode
+    escape_char: Option<char>,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() {
+            return None;
+        }
+
+        let line = self.line;
+        let mut escaped = false;
+        for (idx, ch) in self.input.char_indices() {
+            if ch == '\n' {
+                self.line += 1;
+            }
+            match ch {
+                _ if Some(ch) == self.escape_char && !escaped => escaped = true,
+                '\n' if !escaped => {
+                    let record = &self.input[..idx];
+                    self.input = &self.input[idx + 1..];
+                    return Some((line, record));
+                }
+                _ => escaped = false,
+            }
+        }
+
+        let record = self.input;
+        self.input = "";
+        Some((line, record))
     }
 }
 
@@ -45,48 +288,53 @@ impl<'de> Deserializer<'de> {
         self.input = &self.input[len..];
     }
 
-    //TODO: we can probably do this better by creating a modified version of `get_next_nonescaped_char`.
     fn get_next_delimiter_idx(&self) -> Option<usize> {
-        let mut idx = self.get_next_nonescaped_char(':');
-        if self.in_seq || self.in_map {
-            let comma_idx = self.get_next_nonescaped_char(',');
-            // Choose the smaller of the two
-            idx = match (idx, comma_idx) {
-                (Some(idx), Some(comma_idx)) => Some(std::cmp::min(idx, comma_idx)),
-                (Some(idx), None) => Some(idx),
-                (None, Some(comma_idx)) => Some(comma_idx),
-                (None, None) => None,
-            };
-        }
+        self.find_nonescaped(|ch| self.is_delimiter(ch))
+    }
 
-        if self.in_map {
-            let equals_idx = self.get_next_nonescaped_char('=');
-            // Choose the smaller of the two
-            idx = match (idx, equals_idx) {
-                (Some(idx), Some(equals_idx)) => Some(std::cmp::min(idx, equals_idx)),
-                (Some(idx), None) => Some(idx),
-                (None, Some(equals_idx)) => Some(equals_idx),
-                (None, None) => None,
-            };
+    // Whether `ch` ends the current field, using the same rules as `get_next_delimiter_idx`.
+    fn is_delimiter(&self, ch: char) -> bool {
+        ch == self.config.field_delimiter
+            || ((self.in_seq || self.in_map) && ch == self.config.item_delimiter)
+            || (self.in_map && ch == self.config.key_value_delimiter)
+    }
+
+    // Consume the field if it is the `Config::null_token`.
+    fn at_null_token(&mut self) -> bool {
+        let len = self.get_next_delimiter_idx().unwrap_or(self.input.len());
+        let is_null = self.config.null_token.as_deref() == Some(&self.input[..len]);
+        if is_null {
+            self.shift_input_forward(len);
         }
+        is_null
+    }
 
-        idx
+    // Nothing is escaped in `Config::raw` mode.
+    fn is_escape(&self, ch: char) -> bool {
+        !self.config.raw && ch == self.config.escape_char
     }
 
     fn get_next_nonescaped_char(&self, ch: char) -> Option<usize> {
-        self.input
-            .match_indices(|c| c == ch)
-            .map(|(idx, _)| idx)
-            .filter(|idx| {
-                idx > &0
-                    && self
-                        .input
-                        .chars()
-                        .nth(idx - 1)
-                        .map(|ch| ch != '\\')
-                        .unwrap_or(false)
-            })
-            .next()
+        self.find_nonescaped(|c| c == ch)
+    }
+
+    // A single pass over the input, a backslash escapes whatever follows it,
+    // including another backslash.
+    fn find_nonescaped<P>(&self, predicate: P) -> Option<usize>
+    where
+        P: Fn(char) -> bool,
+    {
+        let mut escaped = false;
+        for (idx, ch) in self.input.char_indices() {
+            if escaped {
+                escaped = false;
+            } else if self.is_escape(ch) {
+                escaped = true;
+            } else if predicate(ch) {
+                return Some(idx);
+            }
+        }
+        None
     }
 
     // Look at the first character in the input without consuming it.
@@ -101,27 +349,61 @@ impl<'de> Deserializer<'de> {
         Ok(ch)
     }
 
-    // Parse the identifier `true` or `false`.
-    fn parse_bool(&mut self) -> Result<bool> {
-        if self.input.starts_with("true") {
-            self.shift_input_forward("true".len());
-            Ok(true)
-        } else if self.input.starts_with("false") {
-            self.shift_input_forward("false".len());
-            Ok(false)
-        } else {
-            Err(Error::ExpectedBoolean)
+    // Consume a delimiter and the `Config::spacing` written after it.
+    fn next_delimiter(&mut self) -> Result<char> {
+        let ch = self.next_char()?;
+        self.skip_spacing();
+        Ok(ch)
+    }
+
+    // Go one level deeper, erroring rather than overflowing the stack on
+    // deeply nested input.
+    fn enter(&mut self) -> Result<()> {
+        if self.depth >= self.config.max_depth {
+            return Err(Error::DepthLimitExceeded);
+        }
+        self.depth += 1;
+        self.in_some = false;
+        Ok(())
+    }
+
+    fn skip_spacing(&mut self) {
+        if let Some(input) = self.input.strip_prefix(self.config.spacing.as_str()) {
+            self.input = input;
         }
     }
 
-    // The various arithmetic operations can overflow and
-    // panic or return bogus data.
+    // Parse the identifier `true` or `false`, which must be the whole field.
+    fn parse_bool(&mut self) -> Result<bool> {
+        let len = self.get_next_delimiter_idx().unwrap_or(self.input.len());
+        let b = match &self.input[..len] {
+            "true" => true,
+            "false" => false,
+            "1" if self.config.bool_as_int => true,
+            "0" if self.config.bool_as_int => false,
+            _ => return Err(Error::ExpectedBoolean),
+        };
+        self.shift_input_forward(len);
+        Ok(b)
+    }
+
+    // Accumulate with checked arithmetic so that input too large for `T` is an
+    // error rather than wrapping around.
     fn parse_unsigned<T>(&mut self) -> Result<T>
     where
-        T: AddAssign<T> + MulAssign<T> + From<u8>,
+        T: CheckedAdd + CheckedMul + From<u8>,
     {
-        let mut int = match self.next_char()? {
-            ch @ '0'..='9' => T::from(ch as u8 - b'0'),
+        let mut int = match self.input.chars().next() {
+            Some(ch @ '0'..='9') => {
+                self.shift_input_forward(1);
+                if ch == '0'
+                    && self.config.strict_numbers
+                    && self.input.starts_with(|ch: char| ch.is_ascii_digit())
+                {
+                    return Err(Error::LeadingZero);
+                }
+                T::from(ch as u8 - b'0')
+            }
             _ => {
                 return Err(Error::ExpectedInteger);
             }
@@ -130,8 +412,10 @@ impl<'de> Deserializer<'de> {
             match self.input.chars().next() {
                 Some(ch @ '0'..='9') => {
                     self.shift_input_forward(1);
-                    int *= T::from(10);
-                    int += T::from(ch as u8 - b'0');
+                    int = int
+                        .checked_mul(&T::from(10))
+                        .and_then(|int| int.checked_add(&T::from(ch as u8 - b'0')))
+                        .ok_or(Error::IntegerOverflow)?;
                 }
                 _ => {
                     return Ok(int);
@@ -140,16 +424,50 @@ impl<'de> Deserializer<'de> {
         }
     }
 
+    // Optional minus sign, delegate to `parse_unsigned` for the magnitude,
+    // negate if negative, then narrow to the requested type.
     fn parse_signed<T>(&mut self) -> Result<T>
     where
-        T: Neg<Output = T> + AddAssign<T> + MulAssign<T> + From<i8>,
+        T: TryFrom<i128>,
+    {
+        let negative = self.input.starts_with('-');
+        if negative {
+            self.shift_input_forward(1);
+        }
+
+        // Accumulate the magnitude unsigned, as the magnitude of a signed
+        // minimum doesn't fit in the positive range.
+        let magnitude = self.parse_unsigned::<u128>()?;
+        let int = if negative {
+            0i128.checked_sub_unsigned(magnitude)
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+        .ok_or(Error::IntegerOverflow)?;
+
+        T::try_from(int).map_err(|_| Error::IntegerOverflow)
+    }
+
+    // Floats are read up to the next delimiter and handed to `str::parse`, which
+    // also accepts the scientific notation the serializer can emit.
+    fn parse_float<T>(&mut self) -> Result<T>
+    where
+        T: FromStr,
     {
-        // Optional minus sign, delegate to `parse_unsigned`, negate if negative.
-        todo!()
+        let len = match self.get_next_delimiter_idx() {
+            Some(idx) => idx,
+            None => self.input.len(),
+        };
+
+        let float = self.input[..len]
+            .parse()
+            .map_err(|_| Error::ExpectedFloat)?;
+        self.shift_input_forward(len);
+        Ok(float)
     }
 
-    // TODO: how do we have it so it can return a &str - use Cow?
-    fn parse_string(&mut self) -> Result<String> {
+    // Borrow straight from the input unless there is something to unescape.
+    fn parse_string(&mut self) -> Result<Cow<'de, str>> {
         let len = match self.get_next_delimiter_idx() {
             Some(idx) => idx,
             None => self.input.len(),
@@ -158,32 +476,119 @@ impl<'de> Deserializer<'de> {
         let s = &self.input[..len];
         self.shift_input_forward(len);
 
-        // Replace escape characters used in UDSV format
-        let mut s = s.replace(r#"\:"#, ":");
-        s = s.replace(r#"\,"#, ",");
-        s = s.replace(r#"\="#, "=");
-        s = s.replace(r#"\\"#, r#"\"#);
+        if self.config.raw || !s.contains(self.config.escape_char) {
+            return Ok(Cow::Borrowed(s));
+        }
+
+        self.unescape(s).map(Cow::Owned)
+    }
+
+    // Replace escape sequences in a single pass. An unknown escape sequence is
+    // kept as is, or is an error with `Config::strict_escapes`.
+    fn unescape(&self, s: &str) -> Result<String> {
+        let escape_char = self.config.escape_char;
+        let mut unescaped = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(ch) = chars.next() {
+            if ch != escape_char {
+                unescaped.push(ch);
+                continue;
+            }
 
-        // Remove an escaped newline
-        s = s.replace("\\\n", "");
+            match chars.next() {
+                Some(ch) if ch == escape_char => unescaped.push(ch),
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some('t') => unescaped.push('\t'),
+                // An escaped newline continues the record on the next line
+                Some('\n') => {}
+                Some(ch)
+                    if ch == self.config.field_delimiter
+                        || ch == self.config.item_delimiter
+                        || ch == self.config.key_value_delimiter =>
+                {
+                    unescaped.push(ch)
+                }
+                Some('x') => match Self::peek_control(chars.as_str()) {
+                    Some(control) => {
+                        unescaped.push(control);
+                        chars.nth(1);
+                    }
+                    None => self.unknown_escape(&mut unescaped, &format!("{}x", escape_char))?,
+                },
+                Some(ch) => {
+                    self.unknown_escape(&mut unescaped, &format!("{}{}", escape_char, ch))?
+                }
+                // A trailing escape character with nothing to escape
+                None => self.unknown_escape(&mut unescaped, &escape_char.to_string())?,
+            }
+        }
+        Ok(unescaped)
+    }
 
-        // Replace escaped printables
-        s = s.replace(r#"\n"#, "\n");
-        s = s.replace(r#"\r"#, "\r");
-        s = s.replace(r#"\t"#, "\t");
+    fn unknown_escape(&self, unescaped: &mut String, sequence: &str) -> Result<()> {
+        if self.config.strict_escapes {
+            return Err(Error::UnknownEscape(sequence.to_owned()));
+        }
+        unescaped.push_str(sequence);
+        Ok(())
+    }
 
-        Ok(s)
+    // The ASCII control character encoded by the two hex digits after `\x`.
+    fn peek_control(s: &str) -> Option<char> {
+        s.get(..2)
+            .filter(|hex| hex.chars().all(|ch| ch.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|byte| byte.is_ascii_control())
+            .map(char::from)
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    // UDSV is not a self-describing format, this is a best effort guess from the
+    // delimiters in the rest of the record. A record with more than one field is
+    // a sequence of fields, otherwise the field is a map, list, or string. It is
+    // enough for `#[serde(flatten)]` and tagged enums, which buffer values
+    // through `deserialize_any`.
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!("UDSV is not a self-describing format")
+        if !self.in_seq && !self.in_map {
+            let len = self
+                .get_next_nonescaped_char(self.config.field_delimiter)
+                .unwrap_or(self.input.len());
+
+            if !self.in_fields && len < self.input.len() {
+                self.in_fields = true;
+                let delim = self.config.field_delimiter;
+                let v = visitor.visit_seq(DelimiterSeparated::new(self, delim)?);
+                self.in_fields = false;
+                return v;
+            }
+
+            let in_field = |idx: Option<usize>| idx.map(|idx| idx < len).unwrap_or(false);
+
+            if in_field(self.get_next_nonescaped_char(self.config.key_value_delimiter)) {
+                return self.deserialize_map(visitor);
+            }
+            if in_field(self.get_next_nonescaped_char(self.config.item_delimiter)) {
+                return self.deserialize_seq(visitor);
+            }
+        }
+
+        if self.at_null_token() {
+            return visitor.visit_none();
+        }
+        if self.config.empty_as_none
+            && (self.input.is_empty() || self.get_next_delimiter_idx() == Some(0))
+        {
+            return visitor.visit_none();
+        }
+
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -221,6 +626,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_i64(self.parse_signed()?)
     }
 
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(self.parse_signed()?)
+    }
+
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -249,30 +661,37 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_u64(self.parse_unsigned()?)
     }
 
-    fn deserialize_f32<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(self.parse_unsigned()?)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        visitor.visit_f32(self.parse_float()?)
     }
 
-    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        visitor.visit_f64(self.parse_float()?)
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        // Parse a string, check that it is one character.
-        let ch = self.parse_string()?;
-        if ch.len() == 1 {
-            visitor.visit_char(ch.chars().next().unwrap())
-        } else {
-            Err(Error::ExpectedChar)
+        // Parse a string, check that it is one character (not one byte).
+        let s = self.parse_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => visitor.visit_char(ch),
+            _ => Err(Error::ExpectedChar),
         }
     }
 
@@ -280,7 +699,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_string(self.parse_string()?)
+        match self.parse_string()? {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
@@ -290,42 +712,56 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_str(visitor)
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::BytesUnsupported)
+        self.deserialize_byte_buf(visitor)
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        Err(Error::BytesUnsupported)
+        let s = self.parse_string()?;
+        visitor.visit_byte_buf(bytes::decode(&s)?)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        // With a null token an empty field is an empty value, or `None` for
+        // the `Some(None)` of a nested option
+        if self.config.null_token.is_some() {
+            let empty = self.input.is_empty() || self.get_next_delimiter_idx() == Some(0);
+            if self.at_null_token() || (self.in_some && empty) {
+                return visitor.visit_none();
+            }
+            let prev = std::mem::replace(&mut self.in_some, true);
+            let v = visitor.visit_some(&mut *self);
+            self.in_some = prev;
+            return v;
+        }
+
         if self.input.is_empty() {
             return visitor.visit_none();
         }
 
         let next_char = self.peek_char()?;
-        match (next_char, self.in_seq, self.in_map) {
-            (':', false, false) => visitor.visit_none(), // Not in a sequence or map
-            (':' | ',', true, false) => visitor.visit_none(), // In a sequence but not in a map
-            (':' | ',' | '=', _, true) => visitor.visit_none(), // In a map and possibly in a sequence
-            _ => visitor.visit_some(self),
+        if self.is_delimiter(next_char) {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
         }
     }
 
+    // Unit is an empty field.
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if self.input.is_empty() {
+        if self.input.is_empty() || self.get_next_delimiter_idx() == Some(0) {
             visitor.visit_unit()
         } else {
             Err(Error::ExpectedEmpty)
@@ -350,9 +786,14 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.in_seq = true;
-        let v = visitor.visit_seq(DelimiterSeparated::new(self, ','));
-        self.in_seq = false;
+        // The inner items would be indistinguishable from the outer ones.
+        if self.in_seq || self.in_map {
+            return Err(Error::NestedCollection);
+        }
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        let delim = self.config.item_delimiter;
+        let v = visitor.visit_seq(DelimiterSeparated::open_ended(self, delim)?);
+        self.in_seq = prev;
         v
     }
 
@@ -360,9 +801,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.in_seq = true;
-        let v = visitor.visit_seq(DelimiterSeparated::new(self, ','));
-        self.in_seq = false;
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        let delim = self.config.item_delimiter;
+        let v = visitor.visit_seq(DelimiterSeparated::new(self, delim)?);
+        self.in_seq = prev;
         v
     }
 
@@ -375,9 +817,10 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.in_seq = true;
-        let v = visitor.visit_seq(DelimiterSeparated::new(self, ','));
-        self.in_seq = false;
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        let delim = self.config.item_delimiter;
+        let v = visitor.visit_seq(DelimiterSeparated::new(self, delim)?);
+        self.in_seq = prev;
         v
     }
 
@@ -385,9 +828,24 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.in_map = true;
-        let v = visitor.visit_map(DelimiterSeparated::new(self, ','));
-        self.in_map = false;
+        if self.in_seq || self.in_map {
+            return Err(Error::NestedCollection);
+        }
+
+        // A field without a `=` isn't a map at all, rather than a map missing
+        // its first `=`
+        let len = self
+            .get_next_nonescaped_char(self.config.field_delimiter)
+            .unwrap_or(self.input.len());
+        let equals_idx = self.get_next_nonescaped_char(self.config.key_value_delimiter);
+        if len > 0 && equals_idx.map_or(true, |idx| idx >= len) {
+            return Err(Error::ExpectedMap);
+        }
+
+        let prev = std::mem::replace(&mut self.in_map, true);
+        let delim = self.config.item_delimiter;
+        let v = visitor.visit_map(DelimiterSeparated::new(self, delim)?);
+        self.in_map = prev;
         v
     }
 
@@ -400,8 +858,17 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        // Do not set `in_seq` here as that is used to stop at commas.
-        visitor.visit_seq(DelimiterSeparated::new(self, ':'))
+        // Do not set `in_seq` here as that is used to stop at commas. A struct
+        // already in a list is flattened like a tuple.
+        if self.in_map {
+            return Err(Error::NestedCollection);
+        }
+        let delim = if self.in_seq {
+            self.config.item_delimiter
+        } else {
+            self.config.field_delimiter
+        };
+        visitor.visit_seq(DelimiterSeparated::new(self, delim)?)
     }
 
     fn deserialize_enum<V>(
@@ -413,21 +880,39 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_enum(Enum::new(self))
+        self.enter()?;
+        let v = visitor.visit_enum(Enum::new(&mut *self));
+        self.depth -= 1;
+        v
     }
 
+    // Enum variant and field names can't be empty.
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if self.input.is_empty() || self.get_next_delimiter_idx() == Some(0) {
+            return Err(Error::ExpectedString);
+        }
         self.deserialize_str(visitor)
     }
 
+    // Fields are positional, so skip one field up to the next delimiter. At the
+    // top level the whole record is ignored, but must still be well formed.
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_any(visitor)
+        if self.depth == 0 {
+            return self.deserialize_any(visitor);
+        }
+
+        let len = match self.get_next_delimiter_idx() {
+            Some(idx) => idx,
+            None => self.input.len(),
+        };
+        self.shift_input_forward(len);
+        visitor.visit_unit()
     }
 }
 
@@ -435,18 +920,44 @@ struct DelimiterSeparated<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
     first: bool,
     delim: char,
+    // Lists are read until they run out of items, so an empty last item can't
+    // be told apart from a stray delimiter. Tuples and structs have a length.
+    open_ended: bool,
 }
 
 impl<'a, 'de> DelimiterSeparated<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>, delim: char) -> Self {
-        DelimiterSeparated {
+    fn new(de: &'a mut Deserializer<'de>, delim: char) -> Result<Self> {
+        de.enter()?;
+        Ok(DelimiterSeparated {
             de,
             first: true,
             delim,
+            open_ended: false,
+        })
+    }
+
+    fn open_ended(de: &'a mut Deserializer<'de>, delim: char) -> Result<Self> {
+        let mut seq = DelimiterSeparated::new(de, delim)?;
+        seq.open_ended = true;
+        Ok(seq)
+    }
+
+    // Found another delimiter where the one between items should be.
+    fn unexpected_delimiter(&self) -> Error {
+        if self.delim == self.de.config.field_delimiter {
+            Error::Syntax
+        } else {
+            Error::ExpectedArrayComma
         }
     }
 }
 
+impl<'a, 'de> Drop for DelimiterSeparated<'a, 'de> {
+    fn drop(&mut self) {
+        self.de.depth -= 1;
+    }
+}
+
 impl<'de, 'a> SeqAccess<'de> for DelimiterSeparated<'a, 'de> {
     type Error = Error;
 
@@ -454,18 +965,79 @@ impl<'de, 'a> SeqAccess<'de> for DelimiterSeparated<'a, 'de> {
     where
         T: DeserializeSeed<'de>,
     {
-        if self.de.input.is_empty() || (self.delim != ':' && self.de.peek_char()? == ':') {
+        let field_delimiter = self.de.config.field_delimiter;
+        if self.de.input.is_empty()
+            || (self.delim != field_delimiter && self.de.peek_char()? == field_delimiter)
+        {
             return Ok(None);
         }
 
-        if !self.first && self.de.next_char()? != self.delim {
-            //TODO: this is not the right error if delim is not a comma
-            return Err(Error::ExpectedArrayComma);
+        // Strictly every item is followed by a delimiter, so an empty last item
+        // is fine
+        let trailing_delimiter = self.open_ended && self.de.config.trailing_delimiter;
+        if trailing_delimiter && self.de.config.strict_trailing_delimiter {
+            let v = seed.deserialize(&mut *self.de)?;
+            if self.de.input.is_empty() || self.de.next_delimiter()? != self.delim {
+                return Err(Error::ExpectedArrayComma);
+            }
+            return Ok(Some(v));
+        }
+
+        if !self.first {
+            if self.de.next_delimiter()? != self.delim {
+                return Err(self.unexpected_delimiter());
+            }
+
+            // A trailing or stray comma at the end of a list, rather than an
+            // empty item
+            if self.open_ended
+                && (self.de.input.is_empty() || self.de.peek_char()? == field_delimiter)
+            {
+                if trailing_delimiter {
+                    return Ok(None);
+                }
+                return Err(Error::ExpectedArrayEnd);
+            }
         }
         self.first = false;
 
         seed.deserialize(&mut *self.de).map(Some)
     }
+
+    // Count the delimiters left in the field, so serde can pre-allocate a list.
+    fn size_hint(&self) -> Option<usize> {
+        if !self.open_ended {
+            return None;
+        }
+
+        let field_delimiter = self.de.config.field_delimiter;
+        let mut escaped = false;
+        let mut delims = 0;
+        let mut empty = true;
+        let mut ends_with_delim = false;
+        for ch in self.de.input.chars() {
+            if ch == field_delimiter && !escaped {
+                break;
+            }
+            ends_with_delim = ch == self.delim && !escaped;
+            if escaped {
+                escaped = false;
+            } else if self.de.is_escape(ch) {
+                escaped = true;
+            } else if ch == self.delim {
+                delims += 1;
+            }
+            empty = false;
+        }
+
+        // The first item isn't preceded by a delimiter, and the last is only
+        // followed by a trailing one
+        let mut len = if self.first { delims + 1 } else { delims };
+        if ends_with_delim && self.de.config.trailing_delimiter {
+            len -= 1;
+        }
+        Some(if empty { 0 } else { len })
+    }
 }
 
 impl<'de, 'a> MapAccess<'de> for DelimiterSeparated<'a, 'de> {
@@ -475,22 +1047,27 @@ impl<'de, 'a> MapAccess<'de> for DelimiterSeparated<'a, 'de> {
     where
         K: DeserializeSeed<'de>,
     {
-        if self.de.input.is_empty() || self.de.peek_char()? == ':' {
+        if self.de.input.is_empty() || self.de.peek_char()? == self.de.config.field_delimiter {
             return Ok(None);
         }
 
-        if !self.first && self.de.next_char()? != ',' {
+        if !self.first && self.de.next_delimiter()? != self.de.config.item_delimiter {
             return Err(Error::ExpectedMapComma);
         }
         self.first = false;
 
-        let len = match self.de.get_next_nonescaped_char('=') {
+        let len = match self
+            .de
+            .get_next_nonescaped_char(self.de.config.key_value_delimiter)
+        {
             Some(idx) => idx,
             None => Err(Error::ExpectedMapEquals)?,
         };
 
         // validate no comma before equals
-        let comma_idx = self.de.get_next_nonescaped_char(',');
+        let comma_idx = self
+            .de
+            .get_next_nonescaped_char(self.de.config.item_delimiter);
         if comma_idx.is_some() && comma_idx.unwrap() < len {
             return Err(Error::ExpectedMapEquals);
         }
@@ -503,18 +1080,16 @@ impl<'de, 'a> MapAccess<'de> for DelimiterSeparated<'a, 'de> {
         V: DeserializeSeed<'de>,
     {
         // Make sure we have parsed until the equals.
-        if self.de.next_char()? != '=' {
+        if self.de.next_delimiter()? != self.de.config.key_value_delimiter {
             return Err(Error::ExpectedMapEquals);
         }
 
-        let len = match self.de.get_next_nonescaped_char(',') {
-            Some(idx) => idx,
-            None => self.de.input.len(),
-        };
-
-        // validate no equals before comma
-        let equals_idx = self.de.get_next_nonescaped_char('=');
-        if equals_idx.is_some() && equals_idx.unwrap() < len {
+        // validate no equals before the comma or colon ending the value
+        let next = self
+            .de
+            .get_next_delimiter_idx()
+            .and_then(|idx| self.de.input[idx..].chars().next());
+        if next == Some(self.de.config.key_value_delimiter) {
             return Err(Error::ExpectedMapComma);
         }
 
@@ -541,12 +1116,25 @@ impl<'de, 'a> EnumAccess<'de> for Enum<'a, 'de> {
         V: DeserializeSeed<'de>,
     {
         let val = seed.deserialize(&mut *self.de)?;
+        Ok((val, self))
+    }
+}
 
-        if self.de.peek_char().map(|ch| ch == ':').unwrap_or(false) {
-            self.de.shift_input_forward(1);
+impl<'a, 'de> Enum<'a, 'de> {
+    // Skip the delimiter between the variant name and its content. A unit
+    // variant has no content, so the delimiter belongs to the next field.
+    fn content(self) -> &'a mut Deserializer<'de> {
+        let field_delimiter = self.de.config.field_delimiter;
+        if self
+            .de
+            .peek_char()
+            .map(|ch| ch == field_delimiter)
+            .unwrap_or(false)
+        {
+            self.de.shift_input_forward(field_delimiter.len_utf8());
+            self.de.skip_spacing();
         }
-
-        Ok((val, self))
+        self.de
     }
 }
 
@@ -561,14 +1149,14 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
     where
         T: DeserializeSeed<'de>,
     {
-        seed.deserialize(self.de)
+        seed.deserialize(self.content())
     }
 
     fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_tuple(self.de, len, visitor)
+        de::Deserializer::deserialize_tuple(self.content(), len, visitor)
     }
 
     fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
@@ -577,7 +1165,7 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
     {
         //TODO: is the empty string correct here? probaby not
         //seems to work though
-        de::Deserializer::deserialize_struct(self.de, "", fields, visitor)
+        de::Deserializer::deserialize_struct(self.content(), "", fields, visitor)
     }
 }
 
@@ -586,9 +1174,16 @@ impl<'de, 'a> VariantAccess<'de> for Enum<'a, 'de> {
 #[cfg(test)]
 mod test {
 
-    use std::collections::HashMap;
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, HashMap};
+    use std::num::{NonZeroI64, NonZeroU32};
 
-    use crate::record_from_str;
+    use crate::{
+        record_from_reader, record_from_slice, record_from_str, record_from_str_with_config,
+        record_to_string, records_from_str, records_from_str_with_config, unescape_field, Config,
+        Deserializer, Error,
+    };
+    use serde::de::IgnoredAny;
     use serde::Deserialize;
 
     #[test]
@@ -598,10 +1193,235 @@ mod test {
         assert_eq!(1, record_from_str::<u16>(v).unwrap());
         assert_eq!(1, record_from_str::<u32>(v).unwrap());
         assert_eq!(1, record_from_str::<u64>(v).unwrap());
+        assert_eq!(1, record_from_str::<u128>(v).unwrap());
 
         let v = "11534";
         assert_eq!(11534, record_from_str::<u16>(v).unwrap());
-        // assert!(from_str::<u8>(v).is_err());
+        assert!(matches!(
+            record_from_str::<u8>(v),
+            Err(Error::IntegerOverflow)
+        ));
+
+        let v = "99999999999";
+        assert!(matches!(
+            record_from_str::<u8>(v),
+            Err(Error::IntegerOverflow)
+        ));
+
+        let v = u64::MAX.to_string();
+        assert_eq!(u64::MAX, record_from_str::<u64>(&v).unwrap());
+
+        let v = (u128::from(u64::MAX) + 1).to_string();
+        assert!(matches!(
+            record_from_str::<u64>(&v),
+            Err(Error::IntegerOverflow)
+        ));
+
+        let v = "340282366920938463463374607431768211455";
+        assert_eq!(u128::MAX, record_from_str::<u128>(v).unwrap());
+    }
+
+    #[test]
+    fn test_signed() {
+        let v = "1";
+        assert_eq!(1, record_from_str::<i8>(v).unwrap());
+        assert_eq!(1, record_from_str::<i16>(v).unwrap());
+        assert_eq!(1, record_from_str::<i32>(v).unwrap());
+        assert_eq!(1, record_from_str::<i64>(v).unwrap());
+        assert_eq!(1, record_from_str::<i128>(v).unwrap());
+
+        let v = "-42";
+        assert_eq!(-42, record_from_str::<i32>(v).unwrap());
+
+        // The magnitude is parsed wide, then checked against the target width
+        assert_eq!(i8::MAX, record_from_str::<i8>("127").unwrap());
+        assert_eq!(i8::MIN, record_from_str::<i8>("-128").unwrap());
+        for v in ["128", "300", "-129"] {
+            assert!(matches!(
+                record_from_str::<i8>(v),
+                Err(Error::IntegerOverflow)
+            ));
+        }
+
+        let v = i128::MAX.to_string();
+        assert_eq!(i128::MAX, record_from_str::<i128>(&v).unwrap());
+        let v = (i128::MIN + 1).to_string();
+        assert_eq!(i128::MIN + 1, record_from_str::<i128>(&v).unwrap());
+        let v = i128::MIN.to_string();
+        assert_eq!(i128::MIN, record_from_str::<i128>(&v).unwrap());
+        let v = "-170141183460469231731687303715884105729";
+        assert!(matches!(
+            record_from_str::<i128>(v),
+            Err(Error::IntegerOverflow)
+        ));
+
+        let v = "-1,2,-3";
+        let expected: Vec<i64> = vec![-1, 2, -3];
+        assert_eq!(expected, record_from_str::<Vec<i64>>(v).unwrap());
+
+        let v = "-1,-2";
+        let expected: Vec<i32> = vec![-1, -2];
+        assert_eq!(expected, record_from_str::<Vec<i32>>(v).unwrap());
+
+        // A minus sign on its own is not an integer
+        let v = "-";
+        assert!(matches!(
+            record_from_str::<i32>(v),
+            Err(Error::ExpectedInteger)
+        ));
+        let v = "1,-";
+        assert!(matches!(
+            record_from_str::<Vec<i32>>(v),
+            Err(Error::ExpectedInteger)
+        ));
+        let v = "--1";
+        assert!(matches!(
+            record_from_str::<i32>(v),
+            Err(Error::ExpectedInteger)
+        ));
+        let v = "1-";
+        assert!(matches!(
+            record_from_str::<i32>(v),
+            Err(Error::TrailingCharacters)
+        ));
+    }
+
+    #[test]
+    fn test_nonzero() {
+        assert_eq!(
+            NonZeroU32::new(7),
+            Some(record_from_str::<NonZeroU32>("7").unwrap())
+        );
+        assert_eq!(
+            NonZeroI64::new(-7),
+            Some(record_from_str::<NonZeroI64>("-7").unwrap())
+        );
+
+        // serde rejects zero after the integer has been parsed
+        let err = record_from_str::<NonZeroU32>("0").unwrap_err();
+        assert!(matches!(err, Error::Message(_)));
+        assert_eq!(
+            "invalid value: integer `0`, expected a nonzero u32",
+            err.to_string()
+        );
+        let err = record_from_str::<NonZeroI64>("-0").unwrap_err();
+        assert_eq!(
+            "invalid value: integer `0`, expected a nonzero i64",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        let v = "1.5";
+        assert_eq!(1.5, record_from_str::<f32>(v).unwrap());
+        assert_eq!(1.5, record_from_str::<f64>(v).unwrap());
+
+        let v = "1.5,2.5";
+        let expected = vec![1.5, 2.5];
+        assert_eq!(expected, record_from_str::<Vec<f64>>(v).unwrap());
+
+        // Only the map value is parsed, not the rest of the map
+        let v = "a=1.5,b=2.5";
+        let expected = HashMap::from([("a".to_owned(), 1.5), ("b".to_owned(), 2.5)]);
+        assert_eq!(
+            expected,
+            record_from_str::<HashMap<String, f64>>(v).unwrap()
+        );
+
+        let v = "a=1.5=2.5";
+        assert!(record_from_str::<HashMap<String, f64>>(v).is_err());
+
+        // Scientific notation, as `to_string` may emit
+        let v = "1e10";
+        assert_eq!(1e10, record_from_str::<f64>(v).unwrap());
+
+        // Round trip through the serializer
+        let v = "3.14";
+        let f = record_from_str::<f64>(v).unwrap();
+        assert_eq!(v, record_to_string(&f).unwrap());
+
+        let v = "1.5x";
+        assert!(matches!(
+            record_from_str::<f64>(v),
+            Err(Error::ExpectedFloat)
+        ));
+    }
+
+    #[test]
+    fn test_bool() {
+        assert!(record_from_str::<bool>("true").unwrap());
+        assert!(!record_from_str::<bool>("false").unwrap());
+
+        for v in ["trueish", "falsey", "True", "1"] {
+            assert!(matches!(
+                record_from_str::<bool>(v),
+                Err(Error::ExpectedBoolean)
+            ));
+        }
+
+        // A bool ends at any delimiter
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            list: Vec<bool>,
+            b: bool,
+        }
+        let v = "true,false:true";
+        let expected = Test {
+            list: vec![true, false],
+            b: true,
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
+        assert!(matches!(
+            record_from_str::<Test>("truex,false:true"),
+            Err(Error::ExpectedBoolean)
+        ));
+    }
+
+    #[test]
+    fn test_char() {
+        assert_eq!('a', record_from_str::<char>("a").unwrap());
+        assert_eq!('ñ', record_from_str::<char>("ñ").unwrap());
+        assert_eq!('字', record_from_str::<char>("字").unwrap());
+
+        assert!(matches!(
+            record_from_str::<char>("ab"),
+            Err(Error::ExpectedChar)
+        ));
+        assert!(matches!(
+            record_from_str::<char>(""),
+            Err(Error::ExpectedChar)
+        ));
+    }
+
+    #[test]
+    fn test_empty_scalar() {
+        assert!(matches!(
+            record_from_str::<u32>(""),
+            Err(Error::ExpectedInteger)
+        ));
+        assert!(matches!(
+            record_from_str::<i32>(""),
+            Err(Error::ExpectedInteger)
+        ));
+        assert!(matches!(
+            record_from_str::<bool>(""),
+            Err(Error::ExpectedBoolean)
+        ));
+        assert!(matches!(
+            record_from_str::<f64>(""),
+            Err(Error::ExpectedFloat)
+        ));
+
+        // An empty field within a record
+        assert!(matches!(
+            record_from_str::<(u32, u32, u32)>("1,,2"),
+            Err(Error::ExpectedInteger)
+        ));
+        assert_eq!(
+            "Expected an integer",
+            record_from_str::<u32>("").unwrap_err().to_string()
+        );
     }
 
     #[test]
@@ -623,16 +1443,567 @@ e"#;
     }
 
     #[test]
-    fn test_seq() {
-        let v = "a,b";
-        let expected = vec!["a", "b"];
-        assert_eq!(expected, record_from_str::<Vec<String>>(v).unwrap());
+    fn test_unescape_field() {
+        let v = r"a\:b\,c\=d\\e\nf";
+        let expected = "a:b,c=d\\e\nf";
+        assert_eq!(expected, unescape_field(v).unwrap());
+
+        // Delimiters are left alone, the whole value is one field
+        let v = "a:b,c";
+        assert_eq!(v, unescape_field(v).unwrap());
+    }
 
-        let v = "a,b&],\nc";
+    #[test]
+    fn test_borrowed_str() {
+        let v = "hello";
+        assert_eq!("hello", record_from_str::<&str>(v).unwrap());
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test<'a> {
+            a: &'a str,
+            b: &'a str,
+        }
+
+        let v = "hello:world";
+        let expected = Test {
+            a: "hello",
+            b: "world",
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
+
+        // Escaped input can't be borrowed
+        let v = r#"a\:b"#;
+        assert!(record_from_str::<&str>(v).is_err());
+        assert_eq!("a:b", record_from_str::<Cow<str>>(v).unwrap());
+    }
+
+    #[test]
+    fn test_reader() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            seq: Vec<String>,
+        }
+
+        let v = "1:a,b".as_bytes();
+        let expected = Test {
+            int: 1,
+            seq: vec!["a".to_owned(), "b".to_owned()],
+        };
+        assert_eq!(expected, record_from_reader(v).unwrap());
+
+        // Types with a lifetime work as long as they don't borrow from the input
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Owned<'a> {
+            txt: Cow<'a, str>,
+        }
+
+        let v = r"a\:b".as_bytes();
+        let expected = Owned {
+            txt: Cow::Borrowed("a:b"),
+        };
+        assert_eq!(expected, record_from_reader(v).unwrap());
+
+        // While the same type borrows from a `str` where it can
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Borrowed<'a> {
+            #[serde(borrow)]
+            txt: Cow<'a, str>,
+        }
+
+        let v = "a";
+        let test = record_from_str::<Borrowed>(v).unwrap();
+        assert!(matches!(test.txt, Cow::Borrowed("a")));
+
+        // Invalid UTF-8 is reported by the reader
+        let v: &[u8] = &[0xff, 0xfe];
+        assert!(matches!(
+            record_from_reader::<_, String>(v),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_slice() {
+        let v = "a,\u{e9}\\:b".as_bytes();
+        let expected = ("a", "\u{e9}:b".to_owned());
+        assert_eq!(expected, record_from_slice::<(&str, String)>(v).unwrap());
+
+        let v = b"a,\xff";
+        let err = record_from_slice::<(String, String)>(v).unwrap_err();
+        assert!(matches!(err, Error::InvalidUtf8(_)));
+        assert_eq!(
+            "Invalid UTF-8: invalid utf-8 sequence of 1 bytes from index 2",
+            err.to_string()
+        );
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    #[test]
+    fn test_records() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            txt: String,
+        }
+
+        let v = "1:a\n2:b\\\nc\n3:d\n";
+        let expected = vec![
+            Test {
+                int: 1,
+                txt: "a".to_owned(),
+            },
+            Test {
+                int: 2,
+                txt: "bc".to_owned(),
+            },
+            Test {
+                int: 3,
+                txt: "d".to_owned(),
+            },
+        ];
+        let records = records_from_str(v).collect::<Result<Vec<Test>, _>>();
+        assert_eq!(expected, records.unwrap());
+
+        // An escaped backslash does not escape the newline after it
+        let v = r"a\\".to_owned() + "\nb";
+        let expected = vec![r"a\".to_owned(), "b".to_owned()];
+        let records = records_from_str(&v).collect::<Result<Vec<String>, _>>();
+        assert_eq!(expected, records.unwrap());
+
+        assert_eq!(0, records_from_str::<String>("").count());
+
+        // A bad record doesn't stop the following records
+        let v = "1\nx\n3";
+        let records = records_from_str::<u32>(v).collect::<Vec<_>>();
+        assert_eq!(3, records.len());
+        assert!(records[1].is_err());
+        assert_eq!(3, *records[2].as_ref().unwrap());
+
+        // Errors give the line and column in the whole input
+        let v = "1:a\n2:b\\\nc\nx:d\n4:e\\\nf:g";
+        let records = records_from_str::<Test>(v).collect::<Vec<_>>();
+        let err = records[2].as_ref().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Position { line: 4, column: 1, error } if matches!(**error, Error::ExpectedInteger)
+        ));
+        assert_eq!("Expected an integer at line 4, column 1", err.to_string());
+        let err = records[3].as_ref().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Position { line: 6, column: 2, error } if matches!(**error, Error::TrailingCharacters)
+        ));
+    }
+
+    #[test]
+    fn test_records_with_config() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            txt: String,
+        }
+
+        // Newlines are escaped with the configured escape character
+        let config = Config {
+            field_delimiter: '|',
+            escape_char: '~',
+            ..Config::default()
+        };
+        let v = "1|a\\\n2|b~\nc\n";
+        let expected = vec![
+            Test {
+                int: 1,
+                txt: "a\\".to_owned(),
+            },
+            Test {
+                int: 2,
+                txt: "bc".to_owned(),
+            },
+        ];
+        let records = records_from_str_with_config(v, config).unwrap();
+        assert_eq!(expected, records.collect::<Result<Vec<Test>, _>>().unwrap());
+
+        let config = Config {
+            allow_trailing_whitespace: true,
+            ..Config::default()
+        };
+        let records = records_from_str_with_config::<u32>("1\r\n2 \n", config).unwrap();
+        assert_eq!(vec![1, 2], records.collect::<Result<Vec<_>, _>>().unwrap());
+
+        let config = Config {
+            item_delimiter: ':',
+            ..Config::default()
+        };
+        assert!(matches!(
+            records_from_str_with_config::<u32>("1", config).err(),
+            Some(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_position() {
+        let mut de = Deserializer::from_str("ab:c\\\nde:f");
+        assert_eq!((1, 1), de.position());
+        String::deserialize(&mut de).unwrap();
+        assert_eq!((1, 3), de.position());
+        de.next_char().unwrap();
+        String::deserialize(&mut de).unwrap();
+        assert_eq!((2, 3), de.position());
+    }
+
+    #[test]
+    fn test_config() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            txt: String,
+            seq: Vec<String>,
+            map: HashMap<String, String>,
+        }
+
+        let config = Config {
+            field_delimiter: '|',
+            item_delimiter: ';',
+            key_value_delimiter: ':',
+            ..Config::default()
+        };
+
+        let v = r"a:b,c|d;e\;f|g:h;i:j\:k";
+        let expected = Test {
+            txt: "a:b,c".to_owned(),
+            seq: vec!["d".to_owned(), "e;f".to_owned()],
+            map: HashMap::from([
+                ("g".to_owned(), "h".to_owned()),
+                ("i".to_owned(), "j:k".to_owned()),
+            ]),
+        };
+        assert_eq!(expected, record_from_str_with_config(v, config).unwrap());
+
+        let config = Config {
+            item_delimiter: ':',
+            ..Config::default()
+        };
+        assert!(matches!(
+            record_from_str_with_config::<String>("a", config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(all(feature = "hex", not(feature = "base64")))]
+    #[test]
+    fn test_bytes() {
+        let v = "00ff10";
+        let expected = vec![0, 255, 16];
+        assert_eq!(
+            expected,
+            record_from_str::<serde_bytes::ByteBuf>(v)
+                .unwrap()
+                .into_vec()
+        );
+
+        // Odd length or non hex digits
+        for v in ["0", "0g", "+1"] {
+            assert!(matches!(
+                record_from_str::<serde_bytes::ByteBuf>(v),
+                Err(Error::ExpectedBytes)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_manual_deserializer() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            txt: String,
+        }
+
+        let mut de = Deserializer::from_str("1:a");
+        let expected = Test {
+            int: 1,
+            txt: "a".to_owned(),
+        };
+        assert_eq!(expected, Test::deserialize(&mut de).unwrap());
+        assert!(de.is_empty());
+        assert!(de.end().is_ok());
+
+        // A leading value, the rest is left for the caller
+        let mut de = Deserializer::from_str("1:a");
+        assert_eq!(1, u32::deserialize(&mut de).unwrap());
+        assert!(!de.is_empty());
+        assert!(matches!(de.end(), Err(Error::TrailingCharacters)));
+    }
+
+    #[test]
+    fn test_builder() {
+        let j = r"a\,b,c";
+        let mut de = Deserializer::builder().in_seq(true).build(j).unwrap();
+        assert_eq!("a,b", String::deserialize(&mut de).unwrap());
+        assert_eq!((1, 5), de.position());
+        assert!(matches!(de.end(), Err(Error::TrailingCharacters)));
+
+        // Lists can't be nested inside a list
+        let mut de = Deserializer::builder().in_seq(true).build(j).unwrap();
+        assert!(matches!(
+            Vec::<String>::deserialize(&mut de),
+            Err(Error::NestedCollection)
+        ));
+
+        let j = r"k\=1=2";
+        let mut de = Deserializer::builder().in_map(true).build(j).unwrap();
+        assert_eq!("k=1", String::deserialize(&mut de).unwrap());
+
+        let config = Config {
+            item_delimiter: ';',
+            ..Config::default()
+        };
+        let j = "a,b;c";
+        let mut de = Deserializer::builder()
+            .config(config)
+            .in_seq(true)
+            .build(j)
+            .unwrap();
+        assert_eq!("a,b", String::deserialize(&mut de).unwrap());
+
+        let config = Config {
+            item_delimiter: ':',
+            ..Config::default()
+        };
+        assert!(matches!(
+            Deserializer::builder().config(config).build(j),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_numbers() {
+        let strict = Config {
+            strict_numbers: true,
+            ..Config::default()
+        };
+
+        for (v, expected) in [("0", 0), ("10", 10), ("007", 7), ("-007", -7)] {
+            assert_eq!(expected, record_from_str::<i32>(v).unwrap());
+        }
+
+        for (v, expected) in [("0", 0), ("10", 10), ("-0", 0), ("-10", -10)] {
+            assert_eq!(
+                expected,
+                record_from_str_with_config::<i32>(v, strict.clone()).unwrap()
+            );
+        }
+        for v in ["007", "00", "-007"] {
+            let err = record_from_str_with_config::<i32>(v, strict.clone()).unwrap_err();
+            assert!(matches!(err, Error::LeadingZero));
+        }
+        let v = "1,007";
+        assert!(matches!(
+            record_from_str_with_config::<(u8, u8)>(v, strict),
+            Err(Error::LeadingZero)
+        ));
+    }
+
+    #[test]
+    fn test_escaped_control() {
+        let v = r"a\x00b\x1fc\x7f";
+        let expected = "a\u{0}b\u{1f}c\u{7f}";
+        assert_eq!(expected, record_from_str::<String>(v).unwrap());
+
+        // Only control characters are decoded
+        let v = r"\x41";
+        assert_eq!(r"\x41", record_from_str::<String>(v).unwrap());
+
+        // An escaped backslash followed by an x is not a hex escape
+        let v = r"\\x00";
+        assert_eq!(r"\x00", record_from_str::<String>(v).unwrap());
+    }
+
+    #[test]
+    fn test_escaped_backslash_before_delimiter() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: String,
+            b: String,
+        }
+
+        // An escaped backslash then a real colon
+        let v = r"a\\:b";
+        let expected = Test {
+            a: r"a\".to_owned(),
+            b: "b".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
+
+        // An escaped colon
+        let v = r"a\:b";
+        assert_eq!("a:b", record_from_str::<String>(v).unwrap());
+
+        // Three backslashes, an escaped backslash then an escaped colon
+        let v = r"a\\\:b:c";
+        let expected = Test {
+            a: r"a\:b".to_owned(),
+            b: "c".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
+
+        let v = r"a\\,b";
+        let expected = vec![r"a\".to_owned(), "b".to_owned()];
+        assert_eq!(expected, record_from_str::<Vec<String>>(v).unwrap());
+
+        // A delimiter at the start of the input is not escaped
+        let v = "a,,b";
+        let expected = vec!["a".to_owned(), "".to_owned(), "b".to_owned()];
+        assert_eq!(expected, record_from_str::<Vec<String>>(v).unwrap());
+    }
+
+    #[test]
+    fn test_long_input() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: String,
+            b: String,
+        }
+
+        // ~100KB of escaped delimiters before each real one
+        let field = r"a\:\,\=\\".repeat(10_000);
+        let v = format!("{}:{}", field, field);
+        let expected = Test {
+            a: "a:,=\\".repeat(10_000),
+            b: "a:,=\\".repeat(10_000),
+        };
+        assert_eq!(expected, record_from_str(&v).unwrap());
+    }
+
+    #[test]
+    fn test_unescape_single_pass() {
+        // An escaped backslash followed by a letter is not a printable escape
+        let v = r"a\\nb\\tc";
+        let expected = r"a\nb\tc";
+        assert_eq!(expected, record_from_str::<String>(v).unwrap());
+
+        // Nor is it an escaped newline
+        let v = "a\\\\\nb";
+        let expected = "a\\\nb";
+        assert_eq!(expected, record_from_str::<String>(v).unwrap());
+
+        // Unescaped output isn't unescaped again
+        let v = r"\\\\\:";
+        let expected = r"\\:";
+        assert_eq!(expected, record_from_str::<String>(v).unwrap());
+    }
+
+    #[test]
+    fn test_strict_escapes() {
+        let config = Config {
+            strict_escapes: true,
+            ..Config::default()
+        };
+
+        // Known escapes are fine either way
+        let v = r"a\:b\,c\=d\\e\nf\rg\th\x00";
+        let expected = "a:b,c=d\\e\nf\rg\th\u{0}";
+        assert_eq!(
+            expected,
+            record_from_str_with_config::<String>(v, config.clone()).unwrap()
+        );
+        assert_eq!(expected, record_from_str::<String>(v).unwrap());
+
+        // Unknown escapes are kept when lenient
+        for v in [r"a\q", r"a\x41", r"a\"] {
+            assert_eq!(v, record_from_str::<String>(v).unwrap());
+        }
+
+        let err = record_from_str_with_config::<String>(r"a\qb", config.clone()).unwrap_err();
+        assert!(matches!(&err, Error::UnknownEscape(sequence) if sequence == r"\q"));
+        for v in [r"a\x41", r"a\"] {
+            assert!(matches!(
+                record_from_str_with_config::<String>(v, config.clone()),
+                Err(Error::UnknownEscape(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_seq() {
+        let v = "a,b";
+        let expected = vec!["a", "b"];
+        assert_eq!(expected, record_from_str::<Vec<String>>(v).unwrap());
+
+        let v = "a,b&],\nc";
         let expected = vec!["a", "b&]", "\nc"];
         assert_eq!(expected, record_from_str::<Vec<String>>(v).unwrap());
     }
 
+    #[test]
+    fn test_seq_size_hint() {
+        struct Hint(Option<usize>, usize);
+
+        impl<'de> Deserialize<'de> for Hint {
+            fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                struct HintVisitor;
+
+                impl<'de> serde::de::Visitor<'de> for HintVisitor {
+                    type Value = Hint;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        f.write_str("a list")
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Hint, A::Error>
+                    where
+                        A: serde::de::SeqAccess<'de>,
+                    {
+                        let hint = seq.size_hint();
+                        let mut len = 0;
+                        while seq.next_element::<IgnoredAny>()?.is_some() {
+                            len += 1;
+                        }
+                        Ok(Hint(hint, len))
+                    }
+                }
+
+                deserializer.deserialize_seq(HintVisitor)
+            }
+        }
+
+        let cases = [("", 0), ("a", 1), (r"a\,b,c", 2), ("a,,b", 3)];
+        for (j, expected) in cases {
+            let Hint(hint, len) = record_from_str(j).unwrap();
+            assert_eq!((Some(expected), expected), (hint, len));
+        }
+
+        // Only the current field is counted
+        #[derive(Deserialize)]
+        struct Test {
+            hint: Hint,
+            _rest: String,
+        }
+        let Hint(hint, len) = record_from_str::<Test>("a,b:c,d").unwrap().hint;
+        assert_eq!((Some(2), 2), (hint, len));
+
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+        for j in ["a,b,", "a,b", r"a,b\,"] {
+            let Hint(hint, len) = record_from_str_with_config(j, config.clone()).unwrap();
+            assert_eq!((Some(2), 2), (hint, len));
+        }
+        let config = Config {
+            strict_trailing_delimiter: true,
+            ..config
+        };
+        let Hint(hint, len) = record_from_str_with_config("a,,", config).unwrap();
+        assert_eq!((Some(2), 2), (hint, len));
+
+        let v: Vec<u32> = (0..100_000).collect();
+        let j = record_to_string(&v).unwrap();
+        assert_eq!(v, record_from_str::<Vec<u32>>(&j).unwrap());
+    }
+
     #[test]
     fn test_tuple() {
         let v = "a,b";
@@ -643,6 +2014,53 @@ e"#;
         let v = r#"a,b\,c"#;
         let expected = ("a".to_owned(), "b,c".to_owned());
         assert_eq!(expected, record_from_str(v).unwrap());
+
+        // The outer tuple keeps stopping at commas after the inner one ends
+        let v = "a,b,c,d";
+        assert!(matches!(
+            record_from_str::<((String, String), String)>(v),
+            Err(Error::TrailingCharacters)
+        ));
+    }
+
+    #[test]
+    fn test_array() {
+        let v = "1,2,3";
+        assert_eq!([1u32, 2, 3], record_from_str::<[u32; 3]>(v).unwrap());
+
+        let v = "1,2";
+        let err = record_from_str::<[u32; 3]>(v).unwrap_err();
+        assert_eq!(
+            "invalid length 2, expected an array of length 3",
+            err.to_string()
+        );
+
+        let v = "1,2,3,4";
+        assert!(matches!(
+            record_from_str::<[u32; 3]>(v),
+            Err(Error::TrailingCharacters)
+        ));
+
+        let v = "";
+        assert_eq!([0u32; 0], record_from_str::<[u32; 0]>(v).unwrap());
+    }
+
+    #[test]
+    fn test_struct_with_seq_of_tuples() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            name: String,
+            pairs: Vec<(u32, String)>,
+            id: u32,
+        }
+
+        let v = r#"a:1,b\,c,2,d:3"#;
+        let expected = Test {
+            name: "a".to_owned(),
+            pairs: vec![(1, "b,c".to_owned()), (2, "d".to_owned())],
+            id: 3,
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
     }
 
     #[test]
@@ -650,6 +2068,38 @@ e"#;
         let v = "a::b";
         assert!(record_from_str::<Option<String>>(v).is_err());
         assert!(record_from_str::<Vec<Option<String>>>(v).is_err());
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            seq: Vec<String>,
+        }
+
+        // Extra fields after the declared fields
+        for v in ["1:a:leftover", "1:a,b:c:d", "1:a:"] {
+            assert!(matches!(
+                record_from_str::<Test>(v),
+                Err(Error::TrailingCharacters)
+            ));
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        enum E {
+            Struct { a: u32 },
+        }
+
+        let v = "Struct:1:leftover";
+        assert!(matches!(
+            record_from_str::<E>(v),
+            Err(Error::TrailingCharacters)
+        ));
+
+        // Too many elements for a tuple
+        let v = "1,2,3";
+        assert!(matches!(
+            record_from_str::<(u32, u32)>(v),
+            Err(Error::TrailingCharacters)
+        ));
     }
 
     #[test]
@@ -692,6 +2142,119 @@ e"#;
         // An ill formed map errors - equal before comma
         let v = r#"a=b=x,c=d"#;
         assert!(record_from_str::<HashMap<String, String>>(v).is_err());
+
+        // Input that isn't map shaped at all
+        for v in ["notamap", r"a\=b", "a,b:c=d"] {
+            let err = record_from_str::<HashMap<String, String>>(v).unwrap_err();
+            assert!(matches!(err, Error::ExpectedMap));
+            assert_eq!(
+                "Expected a `,` separated map of `key=value`",
+                err.to_string()
+            );
+        }
+        let v = "a=b,c";
+        assert!(matches!(
+            record_from_str::<HashMap<String, String>>(v),
+            Err(Error::ExpectedMapEquals)
+        ));
+    }
+
+    #[test]
+    fn test_map_escaped_backslash() {
+        // An escaped backslash doesn't escape the delimiter after it
+        let v = r"a=b\\,c=d\\";
+        let map = BTreeMap::from([
+            ("a".to_owned(), r"b\".to_owned()),
+            ("c".to_owned(), r"d\".to_owned()),
+        ]);
+        assert_eq!(map, record_from_str(v).unwrap());
+
+        let v = r"a=\\\=b\\,c\\=d";
+        let map = BTreeMap::from([
+            ("a".to_owned(), r"\=b\".to_owned()),
+            (r"c\".to_owned(), "d".to_owned()),
+        ]);
+        assert_eq!(map, record_from_str(v).unwrap());
+
+        let v = r"c=d\\=e";
+        assert!(matches!(
+            record_from_str::<BTreeMap<String, String>>(v),
+            Err(Error::ExpectedMapComma)
+        ));
+
+        // The next field may hold another map
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: BTreeMap<String, String>,
+            b: BTreeMap<String, String>,
+        }
+        let v = r"a=b\\:c=d";
+        let expected = Test {
+            a: BTreeMap::from([("a".to_owned(), r"b\".to_owned())]),
+            b: BTreeMap::from([("c".to_owned(), "d".to_owned())]),
+        };
+        assert_eq!(expected, record_from_str(v).unwrap());
+    }
+
+    #[test]
+    fn test_map_empty_value() {
+        // An empty value is an empty string, or `None`, as in a list
+        let v = "a=b,c=";
+        let map = BTreeMap::from([
+            ("a".to_owned(), "b".to_owned()),
+            ("c".to_owned(), String::new()),
+        ]);
+        assert_eq!(map, record_from_str(v).unwrap());
+        let map = BTreeMap::from([
+            ("a".to_owned(), Some("b".to_owned())),
+            ("c".to_owned(), None),
+        ]);
+        assert_eq!(map, record_from_str(v).unwrap());
+
+        let v = "a=,c=d";
+        let map = BTreeMap::from([
+            ("a".to_owned(), None),
+            ("c".to_owned(), Some("d".to_owned())),
+        ]);
+        assert_eq!(map, record_from_str(v).unwrap());
+
+        let v = "a,,b";
+        let expected = vec![Some("a".to_owned()), None, Some("b".to_owned())];
+        assert_eq!(expected, record_from_str::<Vec<Option<String>>>(v).unwrap());
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let v = "1=a,20=b";
+        let expected = HashMap::from([(1, "a".to_owned()), (20, "b".to_owned())]);
+        assert_eq!(
+            expected,
+            record_from_str::<HashMap<u32, String>>(v).unwrap()
+        );
+
+        let v = "-1=a,2=b";
+        let expected = HashMap::from([(-1, "a".to_owned()), (2, "b".to_owned())]);
+        assert_eq!(
+            expected,
+            record_from_str::<HashMap<i32, String>>(v).unwrap()
+        );
+
+        // The key must end at the equals
+        let v = "1x=a";
+        assert!(matches!(
+            record_from_str::<HashMap<u32, String>>(v),
+            Err(Error::ExpectedMapEquals)
+        ));
+
+        #[derive(Deserialize, PartialEq, Eq, Hash, Debug)]
+        enum Key {
+            A,
+            B,
+        }
+
+        let v = "A=1,B=2";
+        let expected = HashMap::from([(Key::A, 1), (Key::B, 2)]);
+        assert_eq!(expected, record_from_str::<HashMap<Key, u32>>(v).unwrap());
     }
 
     #[test]
@@ -724,6 +2287,353 @@ e"#;
         assert_eq!(expected, record_from_str(j).unwrap());
     }
 
+    #[test]
+    fn test_trailing_whitespace() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            seq: Vec<String>,
+        }
+
+        let j = "1:a\n";
+        let expected = Test {
+            int: 1,
+            seq: vec!["a".to_owned()],
+        };
+        // The newline is read as part of the last field, or left over after a
+        // number
+        let with_newline = Test {
+            int: 1,
+            seq: vec!["a\n".to_owned()],
+        };
+        assert_eq!(with_newline, record_from_str(j).unwrap());
+        assert!(matches!(
+            record_from_str::<u32>("1\n"),
+            Err(Error::TrailingCharacters)
+        ));
+
+        let config = Config {
+            allow_trailing_whitespace: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            expected,
+            record_from_str_with_config(j, config.clone()).unwrap()
+        );
+
+        let j = "1 \r\n";
+        assert_eq!(
+            1,
+            record_from_str_with_config::<u32>(j, config.clone()).unwrap()
+        );
+
+        // Escaped whitespace is kept, but not after an escaped backslash
+        let j = "a\\ \n";
+        assert_eq!(
+            "a\\ ",
+            record_from_str_with_config::<String>(j, config.clone()).unwrap()
+        );
+        let j = "a\\\\ \n";
+        assert_eq!(
+            "a\\",
+            record_from_str_with_config::<String>(j, config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_struct_seq_fields() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: Vec<String>,
+            b: Vec<String>,
+            c: Option<Vec<String>>,
+            d: Vec<String>,
+        }
+
+        let j = "a,b:c:d,e:f";
+        let expected = Test {
+            a: vec!["a".to_owned(), "b".to_owned()],
+            b: vec!["c".to_owned()],
+            c: Some(vec!["d".to_owned(), "e".to_owned()]),
+            d: vec!["f".to_owned()],
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        let j = ":a,b::";
+        let expected = Test {
+            a: vec![],
+            b: vec!["a".to_owned(), "b".to_owned()],
+            c: None,
+            d: vec![],
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+    }
+
+    #[test]
+    fn test_stray_comma() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            seq: Vec<String>,
+            txt: String,
+        }
+
+        let j = "1:a,b,:c";
+        assert!(matches!(
+            record_from_str::<Test>(j),
+            Err(Error::ExpectedArrayEnd)
+        ));
+
+        let j = "a,b,";
+        assert!(matches!(
+            record_from_str::<Vec<String>>(j),
+            Err(Error::ExpectedArrayEnd)
+        ));
+        assert!(matches!(
+            record_from_str::<Vec<Option<u32>>>("1,"),
+            Err(Error::ExpectedArrayEnd)
+        ));
+
+        // Unless it is a trailing delimiter
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+        let expected = Test {
+            int: 1,
+            seq: vec!["a".to_owned(), "b".to_owned()],
+            txt: "c".to_owned(),
+        };
+        assert_eq!(
+            expected,
+            record_from_str_with_config("1:a,b,:c", config).unwrap()
+        );
+
+        // Empty items elsewhere are fine
+        let j = "1:,a,,b:c";
+        let expected = Test {
+            int: 1,
+            seq: vec!["".to_owned(), "a".to_owned(), "".to_owned(), "b".to_owned()],
+            txt: "c".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+        // Between struct fields
+        let j = "1,a:b";
+        assert!(matches!(record_from_str::<Test>(j), Err(Error::Syntax)));
+    }
+
+    #[test]
+    fn test_struct_defaults() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            #[serde(default)]
+            txt: String,
+            #[serde(default)]
+            opt: Option<u32>,
+        }
+
+        // Fields missing from the end of the record take their default
+        let j = "1";
+        let expected = Test {
+            int: 1,
+            txt: "".to_owned(),
+            opt: None,
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        let j = "1:a";
+        let expected = Test {
+            int: 1,
+            txt: "a".to_owned(),
+            opt: None,
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        // Without a default a missing field is an error
+        let err = record_from_str::<Test>("").unwrap_err();
+        assert_eq!(
+            "invalid length 0, expected struct Test with 3 elements",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_ignored_any() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            ignored: IgnoredAny,
+            txt: String,
+        }
+
+        let j = r#"1:a\:b,c:d"#;
+        let expected = Test {
+            int: 1,
+            ignored: IgnoredAny,
+            txt: "d".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        let j = "1,2,3";
+        let expected = vec![IgnoredAny, IgnoredAny, IgnoredAny];
+        assert_eq!(expected, record_from_str::<Vec<IgnoredAny>>(j).unwrap());
+
+        // At the top level the whole record is checked and ignored
+        for j in ["1:a,b", "", "a", r"a\:b:c=d,e=f:g"] {
+            assert_eq!(IgnoredAny, record_from_str::<IgnoredAny>(j).unwrap());
+        }
+        for j in ["a=b=c", "a,b,"] {
+            assert!(record_from_str::<IgnoredAny>(j).is_err());
+        }
+    }
+
+    #[test]
+    fn test_flatten() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            #[serde(flatten)]
+            extra: HashMap<String, String>,
+        }
+
+        let j = "a=b,c=d";
+        let expected = Test {
+            extra: HashMap::from([
+                ("a".to_owned(), "b".to_owned()),
+                ("c".to_owned(), "d".to_owned()),
+            ]),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        // Alongside a flattened field the struct is read as a map of field names
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Named {
+            id: String,
+            #[serde(flatten)]
+            inner: Inner,
+            #[serde(flatten)]
+            extra: HashMap<String, String>,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            name: String,
+        }
+
+        let j = "id=1,name=x,a=b";
+        let expected = Named {
+            id: "1".to_owned(),
+            inner: Inner {
+                name: "x".to_owned(),
+            },
+            extra: HashMap::from([("a".to_owned(), "b".to_owned())]),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+    }
+
+    #[test]
+    fn test_empty_as_none() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Test {
+            id: Option<String>,
+            #[serde(flatten)]
+            inner: Inner,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Inner {
+            name: Option<String>,
+        }
+
+        // Inside the flattened struct the type isn't known up front, so the
+        // empty value is read as a string
+        let j = "id=,name=";
+        let expected = Test {
+            id: None,
+            inner: Inner {
+                name: Some("".to_owned()),
+            },
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        let config = Config {
+            empty_as_none: true,
+            ..Config::default()
+        };
+        let expected = Test {
+            id: None,
+            inner: Inner { name: None },
+        };
+        assert_eq!(
+            expected,
+            record_from_str_with_config(j, config.clone()).unwrap()
+        );
+
+        // A plain `Option` is `None` for an empty field either way
+        let j = "a,,b";
+        let expected = vec![Some("a".to_owned()), None, Some("b".to_owned())];
+        assert_eq!(
+            expected,
+            record_from_str_with_config::<Vec<Option<String>>>(j, config).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tagged_enum() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Unit,
+            Newtype(u32),
+            Tuple(u32, u32),
+            Struct { a: u32, b: String },
+        }
+
+        let j = "Newtype:1";
+        let expected = Adjacent::Newtype(1);
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        let j = "Tuple:1,2";
+        let expected = Adjacent::Tuple(1, 2);
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        // serde only reads the content of a struct variant from a map
+        let j = "Struct:a=1,b=x";
+        let expected = Adjacent::Struct {
+            a: 1,
+            b: "x".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+        let j = "Struct:1:x";
+        assert!(record_from_str::<Adjacent>(j).is_err());
+
+        // Without a content field a unit variant can't be read back
+        let j = "Unit";
+        assert!(record_from_str::<Adjacent>(j).is_err());
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        #[serde(tag = "t")]
+        enum Internal {
+            Unit,
+            Struct { a: String, b: String },
+        }
+
+        // The fields are buffered as strings
+        let j = "Struct:x:y";
+        let expected = Internal::Struct {
+            a: "x".to_owned(),
+            b: "y".to_owned(),
+        };
+        assert_eq!(expected, record_from_str(j).unwrap());
+
+        // A lone tag looks like a string, which is an error rather than a panic
+        let j = "Unit";
+        assert!(matches!(
+            record_from_str::<Internal>(j),
+            Err(Error::Message(_))
+        ));
+    }
+
     #[test]
     fn test_enum() {
         #[derive(Deserialize, PartialEq, Debug)]
@@ -757,5 +2667,74 @@ e"#;
         let j = "Opt:";
         let expected = E::Opt(None);
         assert_eq!(expected, record_from_str(j).unwrap());
+
+        let j = "Bogus:1";
+        let err = record_from_str::<E>(j).unwrap_err();
+        assert!(matches!(&err, Error::ExpectedEnum(variant) if variant == "Bogus"));
+        assert_eq!(
+            "Expected an enum variant name, found `Bogus`",
+            err.to_string()
+        );
+
+        let j = "";
+        assert!(matches!(
+            record_from_str::<E>(j),
+            Err(Error::ExpectedString)
+        ));
+        let j = ":1";
+        assert!(matches!(
+            record_from_str::<E>(j),
+            Err(Error::ExpectedString)
+        ));
+    }
+
+    #[test]
+    fn test_max_depth() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        enum Nest {
+            N(Box<Nest>),
+            End,
+        }
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Chain {
+            id: u32,
+            next: Option<Box<Chain>>,
+        }
+
+        let config = Config {
+            max_depth: 3,
+            ..Config::default()
+        };
+
+        let j = "N:N:End";
+        let expected = Nest::N(Box::new(Nest::N(Box::new(Nest::End))));
+        assert_eq!(
+            expected,
+            record_from_str_with_config(j, config.clone()).unwrap()
+        );
+        let j = "N:N:N:End";
+        assert!(matches!(
+            record_from_str_with_config::<Nest>(j, config.clone()),
+            Err(Error::DepthLimitExceeded)
+        ));
+
+        let j = "1:2:";
+        assert!(record_from_str_with_config::<Chain>(j, config.clone()).is_ok());
+        let j = "1:2:3:4:";
+        assert!(matches!(
+            record_from_str_with_config::<Chain>(j, config),
+            Err(Error::DepthLimitExceeded)
+        ));
+
+        // The default is deep enough for anything reasonable, but stops
+        // hostile input before the stack overflows
+        let j = "N:".repeat(100) + "End";
+        assert!(record_from_str::<Nest>(&j).is_ok());
+        let j = "N:".repeat(100_000) + "End";
+        assert!(matches!(
+            record_from_str::<Nest>(&j),
+            Err(Error::DepthLimitExceeded)
+        ));
     }
 }
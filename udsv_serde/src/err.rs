@@ -1,5 +1,5 @@
-use std;
 use std::fmt::{self, Display};
+use std::io;
 
 use serde::{de, ser};
 
@@ -8,13 +8,19 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Message(String),
+    Io(io::Error),
+    InvalidUtf8(std::str::Utf8Error),
 
     Eof,
     Syntax,
+    UnknownEscape(String),
     BytesUnsupported,
+    ExpectedBytes,
     IntegerOverflow,
+    LeadingZero,
     ExpectedBoolean,
     ExpectedInteger,
+    ExpectedFloat,
     ExpectedChar,
     ExpectedString,
     ExpectedEmpty,
@@ -25,8 +31,21 @@ pub enum Error {
     ExpectedMapComma,
     ExpectedMapEquals,
     ExpectedMapEnd,
-    ExpectedEnum,
+    ExpectedEnum(String),
+    NestedCollection,
+    EmptyLastItem,
+    NestedOption,
+    InvalidMapKey,
     TrailingCharacters,
+    DepthLimitExceeded,
+    /// A `Config` option that can't be used, and why.
+    InvalidConfig(&'static str),
+    /// Where in the input `error` occurred, with a 1-based line and column.
+    Position {
+        line: usize,
+        column: usize,
+        error: Box<Error>,
+    },
 }
 
 impl ser::Error for Error {
@@ -39,20 +58,144 @@ impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
     }
+
+    fn unknown_variant(variant: &str, _expected: &'static [&'static str]) -> Self {
+        Error::ExpectedEnum(variant.to_string())
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Message(msg) => formatter.write_str(msg),
+            Error::Io(err) => write!(formatter, "IO error: {}", err),
+            Error::InvalidUtf8(err) => write!(formatter, "Invalid UTF-8: {}", err),
             Error::Eof => formatter.write_str("Unexpected end of input"),
+            Error::Syntax => formatter.write_str("Syntax error in UDSV record"),
+            Error::UnknownEscape(sequence) => {
+                write!(formatter, "Unknown escape sequence `{}`", sequence)
+            }
             Error::BytesUnsupported => formatter
                 .write_str("Serialising bytes is not supported for a human readable format"),
-            _ => formatter.write_str("I haven't implemented this error message yet"),
-            // TODO: Implement the rest of the error messages
-            /* and so forth */
+            Error::ExpectedBytes => formatter.write_str("Expected hex or base64 encoded bytes"),
+            Error::IntegerOverflow => {
+                formatter.write_str("Integer is too large for the target type")
+            }
+            Error::LeadingZero => formatter.write_str("Integer has a leading zero"),
+            Error::ExpectedBoolean => formatter.write_str("Expected `true` or `false`"),
+            Error::ExpectedInteger => formatter.write_str("Expected an integer"),
+            Error::ExpectedFloat => formatter.write_str("Expected a floating point number"),
+            Error::ExpectedChar => formatter.write_str("Expected a single character"),
+            Error::ExpectedString => {
+                formatter.write_str("Expected a string, e.g. an enum variant name")
+            }
+            Error::ExpectedEmpty => formatter.write_str("Expected an empty field for a unit value"),
+            Error::ExpectedArray => formatter.write_str("Expected a `,` separated list"),
+            Error::ExpectedArrayComma => {
+                formatter.write_str("Expected `,` separating list elements")
+            }
+            Error::ExpectedArrayEnd => {
+                formatter.write_str("Expected `:` or end of record after list")
+            }
+            Error::ExpectedMap => {
+                formatter.write_str("Expected a `,` separated map of `key=value`")
+            }
+            Error::ExpectedMapComma => formatter.write_str("Expected `,` separating map items"),
+            Error::ExpectedMapEquals => {
+                formatter.write_str("Expected `=` separating map key and value")
+            }
+            Error::ExpectedMapEnd => formatter.write_str("Expected `:` or end of record after map"),
+            Error::ExpectedEnum(variant) => {
+                write!(
+                    formatter,
+                    "Expected an enum variant name, found `{}`",
+                    variant
+                )
+            }
+            Error::NestedCollection => {
+                formatter.write_str("Lists and maps cannot be nested inside a list or map")
+            }
+            Error::EmptyLastItem => formatter.write_str("The last item of a list can't be empty"),
+            Error::NestedOption => formatter.write_str(
+                "`Some(None)` looks the same as `None`, unless `Config::null_token` is set",
+            ),
+            Error::InvalidMapKey => {
+                formatter.write_str("Map keys must be a single value, not a list, tuple, or struct")
+            }
+            Error::TrailingCharacters => {
+                formatter.write_str("Unexpected trailing characters after record")
+            }
+            Error::DepthLimitExceeded => {
+                formatter.write_str("Record is nested deeper than the configured `max_depth`")
+            }
+            Error::InvalidConfig(reason) => write!(formatter, "Invalid config: {}", reason),
+            Error::Position {
+                line,
+                column,
+                error,
+            } => write!(formatter, "{} at line {}, column {}", error, line, column),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            Error::Position { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+
+    use std::error::Error as _;
+    use std::io;
+
+    use crate::{record_from_str, records_from_str, Error};
+
+    #[test]
+    fn test_source() {
+        let err = Error::from(io::Error::other("disk full"));
+        let source = err.source().unwrap();
+        assert!(source.downcast_ref::<io::Error>().is_some());
+        assert_eq!("disk full", source.to_string());
+
+        let err = records_from_str::<u32>("x").next().unwrap().unwrap_err();
+        let source = err.source().unwrap();
+        assert!(matches!(
+            source.downcast_ref::<Error>(),
+            Some(Error::ExpectedInteger)
+        ));
+        assert!(source.source().is_none());
+
+        assert!(Error::Eof.source().is_none());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<Error>();
+
+        fn parse(s: &str) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(record_from_str(s)?)
+        }
+        assert_eq!(1, parse("1").unwrap());
+        let err = parse("x").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ExpectedInteger)
+        ));
+    }
+}
@@ -1,7 +1,23 @@
+mod bytes;
+mod config;
 mod de;
 mod err;
+#[cfg(test)]
+mod proptests;
 mod ser;
+#[cfg(feature = "value")]
+mod value;
 
-pub use de::{record_from_str, Deserializer};
+pub use config::Config;
+pub use de::{
+    record_from_reader, record_from_slice, record_from_str, record_from_str_with_config,
+    records_from_str, records_from_str_with_config, unescape_field, Deserializer,
+    DeserializerBuilder,
+};
 pub use err::{Error, Result};
-pub use ser::{record_to_string, Serializer};
+pub use ser::{
+    escape_field, record_to_string, record_to_string_with_config, record_to_vec, record_to_writer,
+    serialized_len, serialized_len_with_config, EscapeContext, Serializer,
+};
+#[cfg(feature = "value")]
+pub use value::Value;
@@ -0,0 +1,124 @@
+// Round-trip property tests, `record_from_str(&record_to_string(&x)) == x`
+// for arbitrary values.
+
+use std::collections::HashMap;
+
+use proptest::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    record_from_str, record_from_str_with_config, record_to_string, record_to_string_with_config,
+    serialized_len, Config, Error,
+};
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Inner {
+    a: u32,
+    b: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Outer {
+    id: i64,
+    inner: Inner,
+    tags: Vec<String>,
+    attrs: HashMap<String, String>,
+    score: f64,
+    opt: Option<u32>,
+}
+
+prop_compose! {
+    fn inner()(a in any::<u32>(), b in any::<String>()) -> Inner {
+        Inner { a, b }
+    }
+}
+
+prop_compose! {
+    fn outer()(
+        id in any::<i64>(),
+        inner in inner(),
+        tags in list(),
+        attrs in any::<HashMap<String, String>>(),
+        score in any::<f64>().prop_filter("NaN isn't equal to itself", |f| !f.is_nan()),
+        opt in any::<Option<u32>>(),
+    ) -> Outer {
+        Outer { id, inner, tags, attrs, score, opt }
+    }
+}
+
+// A list may not end with an empty item, see `Error::EmptyLastItem`.
+fn list() -> impl Strategy<Value = Vec<String>> {
+    any::<Vec<String>>().prop_filter("last item is empty", |v| {
+        v.last().map(|s| !s.is_empty()).unwrap_or(true)
+    })
+}
+
+proptest! {
+    #[test]
+    fn prop_string(v in any::<String>()) {
+        let s = record_to_string(&v).unwrap();
+        prop_assert_eq!(record_from_str::<String>(&s).unwrap(), v);
+    }
+
+    #[test]
+    fn prop_int(v in any::<(i64, u64, i128)>()) {
+        let s = record_to_string(&v).unwrap();
+        prop_assert_eq!(record_from_str::<(i64, u64, i128)>(&s).unwrap(), v);
+    }
+
+    #[test]
+    fn prop_seq(v in any::<Vec<String>>()) {
+        let s = record_to_string(&v);
+        if v.last().is_some_and(String::is_empty) {
+            prop_assert!(matches!(s, Err(Error::EmptyLastItem)));
+        } else {
+            prop_assert_eq!(record_from_str::<Vec<String>>(&s.unwrap()).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn prop_seq_trailing_delimiter(v in any::<Vec<String>>()) {
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+        let s = record_to_string_with_config(&v, config.clone()).unwrap();
+        prop_assert_eq!(record_from_str_with_config::<Vec<String>>(&s, config).unwrap(), v);
+    }
+
+    #[test]
+    fn prop_map(v in any::<HashMap<String, String>>()) {
+        let s = record_to_string(&v).unwrap();
+        prop_assert_eq!(record_from_str::<HashMap<String, String>>(&s).unwrap(), v);
+    }
+
+    // `Some("")` is written the same as `None`
+    #[test]
+    fn prop_option(v in any::<Option<String>>()) {
+        let s = record_to_string(&v).unwrap();
+        let expected = v.filter(|s| !s.is_empty());
+        prop_assert_eq!(record_from_str::<Option<String>>(&s).unwrap(), expected);
+    }
+
+    #[test]
+    fn prop_struct(v in outer()) {
+        let s = record_to_string(&v).unwrap();
+        prop_assert_eq!(serialized_len(&v).unwrap(), s.len());
+        prop_assert_eq!(record_from_str::<Outer>(&s).unwrap(), v);
+    }
+
+    #[test]
+    fn prop_struct_config(v in outer(), spacing in "[ ]{0,2}", escape_char in "[\\\\~^]") {
+        let config = Config {
+            field_delimiter: '|',
+            item_delimiter: ';',
+            key_value_delimiter: ':',
+            escape_control: true,
+            spacing,
+            escape_char: escape_char.chars().next().unwrap(),
+            ..Config::default()
+        };
+        let s = record_to_string_with_config(&v, config.clone()).unwrap();
+        prop_assert_eq!(record_from_str_with_config::<Outer>(&s, config).unwrap(), v);
+    }
+}
@@ -1,78 +1,391 @@
+use std::borrow::Cow;
+use std::io;
+use std::mem;
+
 use serde::{ser, Serialize};
 
+use crate::bytes;
+use crate::config::Config;
 use crate::err::{Error, Result};
 
-pub struct Serializer {
-    output: String,
+pub struct Serializer<W = Vec<u8>> {
+    output: Output<W>,
     in_seq: bool,
     in_map: bool,
+    config: Config,
 }
 
 pub fn record_to_string<T>(value: &T) -> Result<String>
 where
     T: Serialize,
 {
-    let mut serializer = Serializer {
-        output: String::new(),
-        in_seq: false,
-        in_map: false,
-    };
+    record_to_string_with_config(value, Config::default())
+}
+
+pub fn record_to_string_with_config<T>(value: &T, config: Config) -> Result<String>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::with_config(config)?;
+    value.serialize(&mut serializer)?;
+    debug_assert!(serializer.is_reset(), "serializer flags left set");
+    Ok(serializer.into_string())
+}
+
+/// Serialize `value` as the UTF-8 bytes of a record.
+pub fn record_to_vec<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    record_to_string(value).map(String::into_bytes)
+}
+
+/// The length in bytes of `value` as a record, e.g. to pre-size a buffer for
+/// many records. The bytes are counted as they are written to `io::sink`,
+/// rather than stored.
+pub fn serialized_len<T>(value: &T) -> Result<usize>
+where
+    T: Serialize,
+{
+    serialized_len_with_config(value, Config::default())
+}
+
+/// The length in bytes of `value` as a record using `config`, which is checked
+/// to be valid.
+pub fn serialized_len_with_config<T>(value: &T, config: Config) -> Result<usize>
+where
+    T: Serialize,
+{
+    let mut serializer = Serializer::from_writer(io::sink(), config)?;
+    value.serialize(&mut serializer)?;
+    debug_assert!(serializer.is_reset(), "serializer flags left set");
+    Ok(serializer.output.len)
+}
+
+/// Serialize `value` as a record straight into `writer`, a piece at a time, so
+/// a file is best wrapped in an `io::BufWriter`. On error part of the record
+/// may already have been written.
+pub fn record_to_writer<W, T>(writer: W, value: &T) -> Result<()>
+where
+    W: io::Write,
+    T: Serialize,
+{
+    let mut serializer = Serializer::from_writer(writer, Config::default())?;
     value.serialize(&mut serializer)?;
-    Ok(serializer.output)
+    debug_assert!(serializer.is_reset(), "serializer flags left set");
+    Ok(())
+}
+
+/// Where a field value is written, which decides the delimiters to escape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscapeContext {
+    Field,
+    Seq,
+    Map,
+}
+
+/// Escape `v` to be written as part of a record by hand.
+pub fn escape_field(v: &str, context: EscapeContext) -> String {
+    let serializer = Serializer {
+        in_seq: context == EscapeContext::Seq,
+        in_map: context == EscapeContext::Map,
+        ..Serializer::new()
+    };
+    serializer.escape_str(v).into_owned()
+}
+
+// Where a record is written, and what the serializer looks back at.
+struct Output<W> {
+    writer: W,
+    // Bytes written so far
+    len: usize,
+    // Characters since the last newline, only tracked for `Config::wrap_width`
+    column: Option<usize>,
 }
 
-// TODO: struct Serializer owns a impl Write not a String see https://github.com/samscott89/serde_qs/blob/main/src/ser.rs
-// pub fn record_to_writer<T,W>(input: &T, writer: &mut W) -> Result<()>
-// where
-// T: Serialize,
-// W: Write,
-// {
-//     input.serialize(&mut Serializer::new(writer))
-// }
+impl<W: io::Write> Output<W> {
+    fn write(&mut self, s: &str) -> Result<()> {
+        self.writer.write_all(s.as_bytes())?;
+        self.len += s.len();
+        if let Some(column) = &mut self.column {
+            *column = match s.rsplit_once('\n') {
+                Some((_, line)) => line.chars().count(),
+                None => *column + s.chars().count(),
+            };
+        }
+        Ok(())
+    }
+
+    fn write_char(&mut self, ch: char) -> Result<()> {
+        self.write(ch.encode_utf8(&mut [0; 4]))
+    }
+}
 
 impl Serializer {
-    //TODO: do we want to escape tabs, returns?
-    fn escape_str(&self, v: &str) -> String {
-        let mut v = v.to_string();
-        // We have to replace the backslashes first, otherwise we will double escape the other characters.
-        v = v.replace('\\', r"\\");
-        v = v.replace(':', r"\:");
-        v = v.replace('\n', r"\n");
+    /// Create a serializer for writing many records into one buffer with
+    /// `serialize_record`.
+    pub fn new() -> Self {
+        Serializer {
+            output: Output {
+                writer: Vec::new(),
+                len: 0,
+                column: None,
+            },
+            in_seq: false,
+            in_map: false,
+            config: Config::default(),
+        }
+    }
 
-        if self.in_seq || self.in_map {
-            v = v.replace(',', r"\,");
+    /// Create a serializer using `config`, which is checked to be valid.
+    pub fn with_config(config: Config) -> Result<Self> {
+        Serializer::from_writer(Vec::new(), config)
+    }
+
+    /// Append `value` as a record followed by a newline. On error nothing is
+    /// appended.
+    pub fn serialize_record<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        let (len, column) = (self.output.len, self.output.column);
+        let result = self.write_record(value);
+        if result.is_err() {
+            self.output.writer.truncate(len);
+            self.output.len = len;
+            self.output.column = column;
         }
+        result
+    }
 
-        if self.in_map {
-            v = v.replace('=', r"\=");
+    /// The records written so far.
+    pub fn into_string(self) -> String {
+        String::from_utf8(self.output.writer).expect("only strings are written")
+    }
+}
+
+impl<W: io::Write> Serializer<W> {
+    /// Create a serializer for writing many records straight into `writer`
+    /// with `write_record`, using `config`, which is checked to be valid.
+    pub fn from_writer(writer: W, config: Config) -> Result<Self> {
+        config.validate()?;
+        Ok(Serializer {
+            output: Output {
+                writer,
+                len: 0,
+                column: config.wrap_width.map(|_| 0),
+            },
+            in_seq: false,
+            in_map: false,
+            config,
+        })
+    }
+
+    /// Write `value` as a record followed by a newline. On error part of the
+    /// record may already have been written.
+    pub fn write_record<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize,
+    {
+        self.in_seq = false;
+        self.in_map = false;
+        value.serialize(&mut *self)?;
+        debug_assert!(self.is_reset(), "serializer flags left set");
+        self.output.write_char('\n')
+    }
+
+    /// The writer, e.g. to flush it once all the records are written.
+    pub fn into_inner(self) -> W {
+        self.output.writer
+    }
+
+    // Every collection restores the flags it set, so they are all unset again
+    // once a whole record has been written.
+    fn is_reset(&self) -> bool {
+        !self.in_seq && !self.in_map
+    }
+
+    // Borrows `v` when nothing needs escaping, which is the common case.
+    fn escape_str<'a>(&self, v: &'a str) -> Cow<'a, str> {
+        if self.config.raw || !v.chars().any(|ch| self.needs_escape(ch)) {
+            return Cow::Borrowed(v);
+        }
+
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+        let escape_char = self.config.escape_char;
+        // Enough for every character to be escaped, other than as `\xNN`
+        let mut escaped = String::with_capacity(v.len() * 2);
+        for ch in v.chars() {
+            if !self.needs_escape(ch) {
+                escaped.push(ch);
+                continue;
+            }
+
+            escaped.push(escape_char);
+            match ch {
+                _ if ch == escape_char || self.is_delimiter(ch) => escaped.push(ch),
+                '\n' => escaped.push('n'),
+                '\r' => escaped.push('r'),
+                '\t' => escaped.push('t'),
+                // Any other control character, with `Config::escape_control`
+                _ => {
+                    escaped.push('x');
+                    escaped.push(DIGITS[ch as usize >> 4] as char);
+                    escaped.push(DIGITS[ch as usize & 0xf] as char);
+                }
+            }
+        }
+        Cow::Owned(escaped)
+    }
+
+    fn needs_escape(&self, ch: char) -> bool {
+        matches!(ch, '\n' | '\r' | '\t')
+            || ch == self.config.escape_char
+            || ch == self.config.field_delimiter
+            || (self.config.escape_control && ch.is_ascii_control() && !self.is_delimiter(ch))
+            || ((self.in_seq || self.in_map) && ch == self.config.item_delimiter)
+            || (self.in_map && ch == self.config.key_value_delimiter)
+    }
+
+    // Break an escaped string over lines of at most `width` characters with
+    // escaped newlines, which are dropped when deserializing. Escape sequences
+    // are kept whole so they are still read back.
+    fn push_wrapped(&mut self, mut v: &str, width: usize) -> Result<()> {
+        let escape_char = self.config.escape_char;
+        let mut column = self.output.column.unwrap_or(0);
+        while !v.is_empty() {
+            let mut chars = v.chars();
+            let len = match (chars.next(), chars.next()) {
+                (Some(ch), Some('x')) if ch == escape_char => 4,
+                (Some(ch), Some(_)) if ch == escape_char => 2,
+                _ => 1,
+            };
+            let end = v
+                .char_indices()
+                .nth(len)
+                .map(|(idx, _)| idx)
+                .unwrap_or(v.len());
+
+            // Leave room for the escape character ending the line
+            if column > 0 && column + len >= width {
+                self.output.write_char(escape_char)?;
+                self.output.write_char('\n')?;
+                column = 0;
+            }
+            self.output.write(&v[..end])?;
+            column += len;
+            v = &v[end..];
+        }
+        Ok(())
+    }
+
+    // Non-finite values are written as `nan`, `inf`, and `-inf`, which
+    // `str::parse` reads back.
+    fn push_float<F>(&mut self, v: F) -> Result<()>
+    where
+        F: ryu::Float + num_traits::Float,
+    {
+        if v.is_nan() {
+            self.output.write("nan")
+        } else if v.is_infinite() {
+            self.output
+                .write(if v.is_sign_positive() { "inf" } else { "-inf" })
+        } else {
+            self.output.write(ryu::Buffer::new().format_finite(v))
+        }
+    }
+
+    fn push_delimiter(&mut self, delim: char) -> Result<()> {
+        self.output.write_char(delim)?;
+        self.output.write(&self.config.spacing)
+    }
+
+    fn struct_delimiter(&self) -> char {
+        if self.in_seq {
+            self.config.item_delimiter
+        } else {
+            self.config.field_delimiter
+        }
+    }
+
+    fn has_unescaped_delimiter(&self, s: &str) -> bool {
+        let mut escaped = false;
+        for ch in s.chars() {
+            if escaped {
+                escaped = false;
+            } else if !self.config.raw && ch == self.config.escape_char {
+                escaped = true;
+            } else if self.is_delimiter(ch) {
+                return true;
+            }
         }
+        false
+    }
+
+    fn is_delimiter(&self, ch: char) -> bool {
+        ch == self.config.field_delimiter
+            || ch == self.config.item_delimiter
+            || ch == self.config.key_value_delimiter
+    }
+}
 
-        v
+impl Default for Serializer {
+    fn default() -> Self {
+        Serializer::new()
     }
 }
 
 //TODO: do we need atomics here?
-pub struct UDSVSeq<'a>(&'a mut Serializer, i32);
-pub struct UDSVMap<'a>(&'a mut Serializer, i32);
-pub struct UDSVStuct<'a>(&'a mut Serializer, i32);
-pub struct UDSVTuple<'a>(&'a mut Serializer, i32);
+// Lists, maps, and tuples hold the flag they replaced so `end` can restore it
+// for the enclosing collection.
+pub struct UDSVSeq<'a, W> {
+    ser: &'a mut Serializer<W>,
+    len: i32,
+    prev_in_seq: bool,
+    // An empty last item would be read back as a stray comma
+    last_empty: bool,
+}
+
+pub struct UDSVMap<'a, W> {
+    ser: &'a mut Serializer<W>,
+    len: i32,
+    prev_in_map: bool,
+}
+
+pub struct UDSVStuct<'a, W> {
+    ser: &'a mut Serializer<W>,
+    len: i32,
+    // Between fields, which is the item delimiter for a struct in a list
+    delim: char,
+}
+
+pub struct UDSVTuple<'a, W> {
+    ser: &'a mut Serializer<W>,
+    len: i32,
+    prev_in_seq: bool,
+}
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl<'a, W: io::Write> ser::Serializer for &'a mut Serializer<W> {
     type Ok = ();
 
     type Error = Error;
 
-    type SerializeSeq = UDSVSeq<'a>;
-    type SerializeTuple = UDSVTuple<'a>;
-    type SerializeTupleStruct = UDSVTuple<'a>;
-    type SerializeTupleVariant = UDSVTuple<'a>;
-    type SerializeMap = UDSVMap<'a>;
-    type SerializeStruct = UDSVStuct<'a>;
-    type SerializeStructVariant = UDSVStuct<'a>;
+    type SerializeSeq = UDSVSeq<'a, W>;
+    type SerializeTuple = UDSVTuple<'a, W>;
+    type SerializeTupleStruct = UDSVTuple<'a, W>;
+    type SerializeTupleVariant = UDSVTuple<'a, W>;
+    type SerializeMap = UDSVMap<'a, W>;
+    type SerializeStruct = UDSVStuct<'a, W>;
+    type SerializeStructVariant = UDSVStuct<'a, W>;
 
     fn serialize_bool(self, v: bool) -> Result<()> {
-        self.output += if v { "true" } else { "false" };
-        Ok(())
+        self.output.write(match (v, self.config.bool_as_int) {
+            (true, false) => "true",
+            (false, false) => "false",
+            (true, true) => "1",
+            (false, true) => "0",
+        })
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
@@ -87,11 +400,12 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         self.serialize_i64(i64::from(v))
     }
 
-    // Not particularly efficient but this is example code anyway. A more
-    // performant approach would be to use the `itoa` crate.
     fn serialize_i64(self, v: i64) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.output.write(itoa::Buffer::new().format(v))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.output.write(itoa::Buffer::new().format(v))
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
@@ -107,17 +421,21 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.output.write(itoa::Buffer::new().format(v))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.output.write(itoa::Buffer::new().format(v))
     }
 
+    // An `f32` is formatted as is, as widening it to an `f64` would print more
+    // digits, e.g. `0.10000000149011612`.
     fn serialize_f32(self, v: f32) -> Result<()> {
-        self.serialize_f64(f64::from(v))
+        self.push_float(v)
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
-        self.output += &v.to_string();
-        Ok(())
+        self.push_float(v)
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
@@ -125,23 +443,32 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        self.output += &self.escape_str(v);
-        Ok(())
+        let escaped = self.escape_str(v);
+        match self.config.wrap_width {
+            Some(width) => self.push_wrapped(&escaped, width),
+            None => self.output.write(&escaped),
+        }
     }
 
-    fn serialize_bytes(self, _v: &[u8]) -> Result<()> {
-        Err(Error::BytesUnsupported)
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.serialize_str(&bytes::encode(v)?)
     }
 
     fn serialize_none(self) -> Result<()> {
-        self.serialize_unit()
+        match &self.config.null_token {
+            Some(token) => self.output.write(token),
+            None => Ok(()),
+        }
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(self)
+        value.serialize(SomeValue {
+            ser: self,
+            nested: false,
+        })
     }
 
     fn serialize_unit(self) -> Result<()> {
@@ -179,19 +506,32 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         T: ?Sized + Serialize,
     {
         variant.serialize(&mut *self)?;
-        self.output += ":";
+        self.push_delimiter(self.config.field_delimiter)?;
         value.serialize(&mut *self)?;
         Ok(())
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        self.in_seq = true;
-        Ok(UDSVSeq(self, 0))
+        // The inner items would be indistinguishable from the outer ones.
+        if self.in_seq || self.in_map {
+            return Err(Error::NestedCollection);
+        }
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        Ok(UDSVSeq {
+            ser: self,
+            len: 0,
+            prev_in_seq: prev,
+            last_empty: false,
+        })
     }
 
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        self.in_seq = true;
-        Ok(UDSVTuple(self, 0))
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        Ok(UDSVTuple {
+            ser: self,
+            len: 0,
+            prev_in_seq: prev,
+        })
     }
 
     fn serialize_tuple_struct(
@@ -210,18 +550,39 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
         variant.serialize(&mut *self)?;
-        self.output += ":";
-        self.in_seq = true;
-        Ok(UDSVTuple(self, 0))
+        self.push_delimiter(self.config.field_delimiter)?;
+        let prev = std::mem::replace(&mut self.in_seq, true);
+        Ok(UDSVTuple {
+            ser: self,
+            len: 0,
+            prev_in_seq: prev,
+        })
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
-        self.in_map = true;
-        Ok(UDSVMap(self, 0))
+        if self.in_seq || self.in_map {
+            return Err(Error::NestedCollection);
+        }
+        let prev = std::mem::replace(&mut self.in_map, true);
+        Ok(UDSVMap {
+            ser: self,
+            len: 0,
+            prev_in_map: prev,
+        })
     }
 
+    // A struct in a list is flattened like a tuple. A map value can't be, as
+    // its fields would look like more entries.
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
-        Ok(UDSVStuct(self, 0))
+        if self.in_map {
+            return Err(Error::NestedCollection);
+        }
+        let delim = self.struct_delimiter();
+        Ok(UDSVStuct {
+            ser: self,
+            len: 0,
+            delim,
+        })
     }
 
     fn serialize_struct_variant(
@@ -232,12 +593,205 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
         variant.serialize(&mut *self)?;
-        self.output += ":";
-        Ok(UDSVStuct(self, 0))
+        self.push_delimiter(self.config.field_delimiter)?;
+        let delim = self.struct_delimiter();
+        Ok(UDSVStuct {
+            ser: self,
+            len: 0,
+            delim,
+        })
+    }
+}
+
+// The value of a `Some`, to catch a `Some(None)`, which would look the same as
+// `None`. With a `Config::null_token` it can be told apart as an empty field,
+// but not `Some(Some(None))`. Anything else is written as usual.
+pub struct SomeValue<'a, W> {
+    ser: &'a mut Serializer<W>,
+    nested: bool,
+}
+
+impl<'a, W: io::Write> ser::Serializer for SomeValue<'a, W> {
+    type Ok = ();
+
+    type Error = Error;
+
+    type SerializeSeq = UDSVSeq<'a, W>;
+    type SerializeTuple = UDSVTuple<'a, W>;
+    type SerializeTupleStruct = UDSVTuple<'a, W>;
+    type SerializeTupleVariant = UDSVTuple<'a, W>;
+    type SerializeMap = UDSVMap<'a, W>;
+    type SerializeStruct = UDSVStuct<'a, W>;
+    type SerializeStructVariant = UDSVStuct<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.ser.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.ser.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.ser.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.ser.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.ser.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<()> {
+        self.ser.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.ser.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.ser.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.ser.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.ser.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<()> {
+        self.ser.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.ser.serialize_f32(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.ser.serialize_f64(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.ser.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.ser.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.ser.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        if self.nested || self.ser.config.null_token.is_none() {
+            return Err(Error::NestedOption);
+        }
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(SomeValue {
+            ser: self.ser,
+            nested: true,
+        })
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.ser.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
+        self.ser.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.ser
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        self.ser
+            .serialize_newtype_variant(name, variant_index, variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        self.ser.serialize_seq(len)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        self.ser.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        self.ser.serialize_tuple_struct(name, len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.ser
+            .serialize_tuple_variant(name, variant_index, variant, len)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.ser.serialize_map(len)
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        self.ser.serialize_struct(name, len)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.ser
+            .serialize_struct_variant(name, variant_index, variant, len)
     }
 }
 
-impl<'a> ser::SerializeSeq for UDSVSeq<'a> {
+impl<'a, W: io::Write> ser::SerializeSeq for UDSVSeq<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -245,20 +799,30 @@ impl<'a> ser::SerializeSeq for UDSVSeq<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ",";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.ser.config.item_delimiter)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        let len = self.ser.output.len;
+        value.serialize(&mut *self.ser)?;
+        self.last_empty = self.ser.output.len == len;
+        Ok(())
     }
 
     fn end(self) -> Result<()> {
-        self.0.in_seq = false;
+        self.ser.in_seq = self.prev_in_seq;
+        if self.ser.config.trailing_delimiter {
+            if self.len > 0 {
+                self.ser.output.write_char(self.ser.config.item_delimiter)?;
+            }
+        } else if self.last_empty {
+            return Err(Error::EmptyLastItem);
+        }
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTuple for UDSVTuple<'a> {
+impl<'a, W: io::Write> ser::SerializeTuple for UDSVTuple<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -266,20 +830,20 @@ impl<'a> ser::SerializeTuple for UDSVTuple<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ",";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.ser.config.item_delimiter)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.0.in_seq = false;
+        self.ser.in_seq = self.prev_in_seq;
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for UDSVTuple<'a> {
+impl<'a, W: io::Write> ser::SerializeTupleStruct for UDSVTuple<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -287,20 +851,20 @@ impl<'a> ser::SerializeTupleStruct for UDSVTuple<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ",";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.ser.config.item_delimiter)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.0.in_seq = false;
+        self.ser.in_seq = self.prev_in_seq;
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for UDSVTuple<'a> {
+impl<'a, W: io::Write> ser::SerializeTupleVariant for UDSVTuple<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -308,20 +872,20 @@ impl<'a> ser::SerializeTupleVariant for UDSVTuple<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ",";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.ser.config.item_delimiter)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.0.in_seq = false;
+        self.ser.in_seq = self.prev_in_seq;
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeMap for UDSVMap<'a> {
+impl<'a, W: io::Write> ser::SerializeMap for UDSVMap<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -329,28 +893,53 @@ impl<'a> ser::SerializeMap for UDSVMap<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ",";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.ser.config.item_delimiter)?;
+        }
+        self.len += 1;
+
+        // A key written with delimiters, e.g. a tuple or struct, can't be read
+        // back as the `k=v` structure would break, so it is checked in a buffer
+        // before being written.
+        let mut key_ser = Serializer {
+            output: Output {
+                writer: Vec::new(),
+                len: 0,
+                column: self.ser.output.column,
+            },
+            in_seq: self.ser.in_seq,
+            in_map: self.ser.in_map,
+            config: mem::take(&mut self.ser.config),
+        };
+        let result = key.serialize(&mut key_ser);
+        self.ser.config = mem::take(&mut key_ser.config);
+        match result {
+            Err(Error::NestedCollection) => return Err(Error::InvalidMapKey),
+            result => result?,
         }
-        self.1 += 1;
-        key.serialize(&mut *self.0)
+        let key = key_ser.into_string();
+        if self.ser.has_unescaped_delimiter(&key) {
+            return Err(Error::InvalidMapKey);
+        }
+        self.ser.output.write(&key)
     }
 
     fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        self.0.output += "=";
-        value.serialize(&mut *self.0)
+        self.ser
+            .push_delimiter(self.ser.config.key_value_delimiter)?;
+        value.serialize(&mut *self.ser)
     }
 
     fn end(self) -> Result<()> {
-        self.0.in_map = false;
+        self.ser.in_map = self.prev_in_map;
         Ok(())
     }
 }
 
-impl<'a> ser::SerializeStruct for UDSVStuct<'a> {
+impl<'a, W: io::Write> ser::SerializeStruct for UDSVStuct<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -358,11 +947,21 @@ impl<'a> ser::SerializeStruct for UDSVStuct<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ":";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.delim)?;
+        }
+        self.len += 1;
+        value.serialize(&mut *self.ser)
+    }
+
+    // Fields are positional, so a field skipped with `skip_serializing_if` is
+    // left empty rather than shifting the fields after it.
+    fn skip_field(&mut self, _key: &'static str) -> Result<()> {
+        if self.len > 0 {
+            self.ser.push_delimiter(self.delim)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        Ok(())
     }
 
     fn end(self) -> Result<()> {
@@ -370,7 +969,7 @@ impl<'a> ser::SerializeStruct for UDSVStuct<'a> {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for UDSVStuct<'a> {
+impl<'a, W: io::Write> ser::SerializeStructVariant for UDSVStuct<'a, W> {
     type Ok = ();
     type Error = Error;
 
@@ -378,11 +977,19 @@ impl<'a> ser::SerializeStructVariant for UDSVStuct<'a> {
     where
         T: ?Sized + Serialize,
     {
-        if self.1 > 0 {
-            self.0.output += ":";
+        if self.len > 0 {
+            self.ser.push_delimiter(self.delim)?;
+        }
+        self.len += 1;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn skip_field(&mut self, _key: &'static str) -> Result<()> {
+        if self.len > 0 {
+            self.ser.push_delimiter(self.delim)?;
         }
-        self.1 += 1;
-        value.serialize(&mut *self.0)
+        self.len += 1;
+        Ok(())
     }
 
     fn end(self) -> Result<()> {
@@ -395,16 +1002,248 @@ impl<'a> ser::SerializeStructVariant for UDSVStuct<'a> {
 #[cfg(test)]
 mod test {
 
-    use crate::record_to_string;
-    use serde::Serialize;
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::{
+        escape_field, record_from_str, record_from_str_with_config, record_to_string,
+        record_to_string_with_config, record_to_vec, record_to_writer, records_from_str,
+        serialized_len, serialized_len_with_config, Config, Error, EscapeContext, Serializer,
+    };
+    use serde::{Deserialize, Serialize};
 
     #[test]
-    fn test_escaped_str() {
-        let v = "a:b";
-        let expected = r#"a\:b"#;
-        assert_eq!(record_to_string(&v).unwrap(), expected);
-
-        let v = r"a\b";
+    fn test_int() {
+        assert_eq!(record_to_string(&-1i8).unwrap(), "-1");
+        assert_eq!(record_to_string(&1u8).unwrap(), "1");
+        assert_eq!(
+            record_to_string(&i128::MIN).unwrap(),
+            "-170141183460469231731687303715884105728"
+        );
+        assert_eq!(
+            record_to_string(&u128::MAX).unwrap(),
+            "340282366920938463463374607431768211455"
+        );
+    }
+
+    #[test]
+    fn test_number_round_trip() {
+        let v = record_to_string(&i8::MIN).unwrap();
+        assert_eq!(v, "-128");
+        assert_eq!(record_from_str::<i8>(&v).unwrap(), i8::MIN);
+
+        let v = record_to_string(&i64::MIN).unwrap();
+        assert_eq!(v, "-9223372036854775808");
+        assert_eq!(record_from_str::<i64>(&v).unwrap(), i64::MIN);
+
+        let v = record_to_string(&i64::MAX).unwrap();
+        assert_eq!(v, "9223372036854775807");
+        assert_eq!(record_from_str::<i64>(&v).unwrap(), i64::MAX);
+
+        let v = record_to_string(&i128::MIN).unwrap();
+        assert_eq!(record_from_str::<i128>(&v).unwrap(), i128::MIN);
+
+        let v = record_to_string(&u64::MAX).unwrap();
+        assert_eq!(v, "18446744073709551615");
+        assert_eq!(record_from_str::<u64>(&v).unwrap(), u64::MAX);
+
+        let v = record_to_string(&f64::MAX).unwrap();
+        assert_eq!(v, "1.7976931348623157e308");
+        assert_eq!(record_from_str::<f64>(&v).unwrap(), f64::MAX);
+
+        let v = record_to_string(&f64::MIN_POSITIVE).unwrap();
+        assert_eq!(record_from_str::<f64>(&v).unwrap(), f64::MIN_POSITIVE);
+
+        let v = record_to_string(&1.5f64).unwrap();
+        assert_eq!(v, "1.5");
+
+        let v = record_to_string(&0.1f32).unwrap();
+        assert_eq!(v, "0.1");
+        assert_eq!(record_from_str::<f32>(&v).unwrap(), 0.1f32);
+
+        let v = record_to_string(&f32::MAX).unwrap();
+        assert_eq!(v, "3.4028235e38");
+        assert_eq!(record_from_str::<f32>(&v).unwrap(), f32::MAX);
+    }
+
+    #[test]
+    fn test_float_struct() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Point {
+            x: f64,
+            y: f64,
+        }
+
+        let p = Point { x: 1.5, y: 2.5 };
+        let expected = "1.5:2.5";
+        assert_eq!(record_to_string(&p).unwrap(), expected);
+        assert_eq!(record_from_str::<Point>(expected).unwrap(), p);
+
+        let p = Point { x: -1e-7, y: 1e20 };
+        let expected = "-1e-7:1e20";
+        assert_eq!(record_to_string(&p).unwrap(), expected);
+        assert_eq!(record_from_str::<Point>(expected).unwrap(), p);
+
+        // In a list the fields end at a comma instead
+        let v = vec![Point { x: 0.5, y: 1.0 }, Point { x: 2.0, y: -3.5 }];
+        let expected = "0.5,1.0,2.0,-3.5";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<Point>>(expected).unwrap(), v);
+
+        let config = Config {
+            spacing: " ".to_owned(),
+            ..Config::default()
+        };
+        let p = Point { x: 1.5, y: 2.5 };
+        let expected = "1.5: 2.5";
+        assert_eq!(
+            record_to_string_with_config(&p, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Point>(expected, config).unwrap(),
+            p
+        );
+
+        assert!(matches!(
+            record_from_str::<Point>("1.5:x"),
+            Err(Error::ExpectedFloat)
+        ));
+    }
+
+    #[test]
+    fn test_non_finite_float() {
+        let v = record_to_string(&f64::NAN).unwrap();
+        assert_eq!(v, "nan");
+        assert!(record_from_str::<f64>(&v).unwrap().is_nan());
+
+        let v = record_to_string(&f64::INFINITY).unwrap();
+        assert_eq!(v, "inf");
+        assert_eq!(record_from_str::<f64>(&v).unwrap(), f64::INFINITY);
+
+        let v = record_to_string(&f64::NEG_INFINITY).unwrap();
+        assert_eq!(v, "-inf");
+        assert_eq!(record_from_str::<f64>(&v).unwrap(), f64::NEG_INFINITY);
+
+        let v = record_to_string(&f32::NAN).unwrap();
+        assert_eq!(v, "nan");
+        assert!(record_from_str::<f32>(&v).unwrap().is_nan());
+
+        let v = record_to_string(&f32::NEG_INFINITY).unwrap();
+        assert_eq!(v, "-inf");
+        assert_eq!(record_from_str::<f32>(&v).unwrap(), f32::NEG_INFINITY);
+
+        let v = vec![f64::NEG_INFINITY, 1.0, f64::INFINITY];
+        let expected = "-inf,1.0,inf";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<f64>>(expected).unwrap(), v);
+    }
+
+    #[test]
+    fn test_char_round_trip() {
+        for ch in [':', ',', '=', '\\'] {
+            let v = record_to_string(&ch).unwrap();
+            assert_eq!(record_from_str::<char>(&v).unwrap(), ch);
+        }
+
+        // Inside a sequence the comma is escaped too
+        let chars = vec![':', ',', '=', '\\'];
+        let v = record_to_string(&chars).unwrap();
+        assert_eq!(v, r"\:,\,,=,\\");
+        assert_eq!(record_from_str::<Vec<char>>(&v).unwrap(), chars);
+    }
+
+    #[test]
+    fn test_collect_str() {
+        struct Time(u32, u32);
+
+        impl std::fmt::Display for Time {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{:02}:{:02}", self.0, self.1)
+            }
+        }
+
+        impl Serialize for Time {
+            fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        let v = (Time(9, 5), Time(12, 30));
+        let expected = r"09\:05,12\:30";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<(String, String)>(expected).unwrap(),
+            ("09:05".to_owned(), "12:30".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_net() {
+        let v = Ipv4Addr::new(127, 0, 0, 1);
+        let expected = "127.0.0.1";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Ipv4Addr>(expected).unwrap(), v);
+
+        let v = Ipv6Addr::LOCALHOST;
+        let expected = r"\:\:1";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Ipv6Addr>(expected).unwrap(), v);
+
+        let v: SocketAddr = "[::1]:8080".parse().unwrap();
+        let expected = r"[\:\:1]\:8080";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<SocketAddr>(expected).unwrap(), v);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            ip: IpAddr,
+            peers: Vec<SocketAddr>,
+            port: u16,
+        }
+        let test = Test {
+            ip: IpAddr::V6("fe80::1".parse().unwrap()),
+            peers: vec![
+                "10.0.0.1:80".parse().unwrap(),
+                "[2001:db8::2]:443".parse().unwrap(),
+            ],
+            port: 22,
+        };
+        let expected = r"fe80\:\:1:10.0.0.1\:80,[2001\:db8\:\:2]\:443:22";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        // A field delimiter that isn't in addresses avoids the escaping
+        let config = Config {
+            field_delimiter: '|',
+            ..Config::default()
+        };
+        let expected = "fe80::1|10.0.0.1:80,[2001:db8::2]:443|22";
+        assert_eq!(
+            record_to_string_with_config(&test, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Test>(expected, config).unwrap(),
+            test
+        );
+    }
+
+    #[test]
+    fn test_escaped_str() {
+        let v = "a:b";
+        let expected = r#"a\:b"#;
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+
+        let v = r"a\b";
         let expected = r"a\\b";
         assert_eq!(record_to_string(&v).unwrap(), expected);
 
@@ -413,10 +1252,810 @@ b";
         let expected = r"a\nb";
         assert_eq!(record_to_string(&v).unwrap(), expected);
 
-        // commas and equals should not be escaped in a plain string
-        let v = r"a,b=c";
-        let expected = r"a,b=c";
-        assert_eq!(record_to_string(&v).unwrap(), expected);
+        let v = "a\tb\rc";
+        let expected = r"a\tb\rc";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<String>(expected).unwrap(), v);
+
+        // commas and equals should not be escaped in a plain string
+        let v = r"a,b=c";
+        let expected = r"a,b=c";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_backslashes() {
+        let cases = [
+            (r"\", r"\\"),
+            (r"\\", r"\\\\"),
+            (r"\\\", r"\\\\\\"),
+            (r"a\", r"a\\"),
+            (r"\b", r"\\b"),
+            (r"\n", r"\\n"),
+            (r"\:", r"\\\:"),
+        ];
+        for (v, expected) in cases {
+            let output = record_to_string(&v).unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(record_from_str::<String>(&output).unwrap(), v);
+        }
+
+        // Before a delimiter, in a list and as the last field
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            list: Vec<String>,
+            last: String,
+        }
+        let test = Test {
+            list: vec![r"\".to_owned(), r"a\".to_owned()],
+            last: r"\".to_owned(),
+        };
+        let expected = r"\\,a\\:\\";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_escape_borrows() {
+        let ser = Serializer::new();
+        assert!(matches!(ser.escape_str("a,b=c"), Cow::Borrowed("a,b=c")));
+        assert!(matches!(ser.escape_str("a:b"), Cow::Owned(v) if v == r"a\:b"));
+
+        let ser = Serializer {
+            in_map: true,
+            ..Serializer::new()
+        };
+        assert!(matches!(ser.escape_str("abc"), Cow::Borrowed("abc")));
+        assert!(matches!(ser.escape_str("a=b"), Cow::Owned(v) if v == r"a\=b"));
+    }
+
+    #[test]
+    fn test_escape_field() {
+        let v = "a:b\\c\nd";
+        let expected = r"a\:b\\c\nd";
+        assert_eq!(escape_field(v, EscapeContext::Field), expected);
+
+        let v = r"a,b=c";
+        assert_eq!(escape_field(v, EscapeContext::Field), r"a,b=c");
+        assert_eq!(escape_field(v, EscapeContext::Seq), r"a\,b=c");
+        assert_eq!(escape_field(v, EscapeContext::Map), r"a\,b\=c");
+    }
+
+    #[test]
+    fn test_config() {
+        #[derive(Serialize)]
+        struct Test {
+            txt: &'static str,
+            seq: Vec<&'static str>,
+            map: BTreeMap<&'static str, &'static str>,
+        }
+
+        let config = Config {
+            field_delimiter: '|',
+            item_delimiter: ';',
+            key_value_delimiter: ':',
+            ..Config::default()
+        };
+
+        let test = Test {
+            txt: "a:b,c|",
+            seq: vec!["d", "e;f"],
+            map: BTreeMap::from([("g", "h"), ("i", "j:k")]),
+        };
+        let expected = r"a:b,c\||d;e\;f|g:h;i:j\:k";
+        assert_eq!(
+            record_to_string_with_config(&test, config).unwrap(),
+            expected
+        );
+
+        let config = Config {
+            key_value_delimiter: ',',
+            ..Config::default()
+        };
+        assert!(matches!(
+            record_to_string_with_config(&"a", config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_trailing_delimiter() {
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+
+        let cases: [(Vec<String>, &str); 5] = [
+            (vec![], ""),
+            (vec!["a".to_owned()], "a,"),
+            (vec!["a".to_owned(), "b,c".to_owned()], r"a,b\,c,"),
+            (vec![String::new()], ","),
+            (vec!["a".to_owned(), String::new()], "a,,"),
+        ];
+        for (v, expected) in cases {
+            let output = record_to_string_with_config(&v, config.clone()).unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(
+                record_from_str_with_config::<Vec<String>>(expected, config.clone()).unwrap(),
+                v
+            );
+        }
+
+        // Only lists have one, not tuples or maps
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            list: Vec<u32>,
+            pair: (u32, u32),
+            map: BTreeMap<String, u32>,
+        }
+        let test = Test {
+            list: vec![1],
+            pair: (2, 3),
+            map: BTreeMap::from([("a".to_owned(), 4)]),
+        };
+        let expected = "1,:2,3:a=4";
+        assert_eq!(
+            record_to_string_with_config(&test, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Test>(expected, config.clone()).unwrap(),
+            test
+        );
+
+        // The trailing delimiter is optional, unless it is strict
+        assert_eq!(
+            record_from_str_with_config::<Vec<u32>>("1,2", config.clone()).unwrap(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            record_from_str_with_config::<Test>("1:2,3:a=4", config.clone()).unwrap(),
+            test
+        );
+
+        let config = Config {
+            strict_trailing_delimiter: true,
+            ..config
+        };
+        assert_eq!(
+            record_from_str_with_config::<Vec<u32>>("1,2,", config.clone()).unwrap(),
+            vec![1, 2]
+        );
+        assert!(matches!(
+            record_from_str_with_config::<Vec<u32>>("1,2", config.clone()),
+            Err(Error::ExpectedArrayComma)
+        ));
+        assert!(record_from_str_with_config::<Test>("1:2,3:a=4", config).is_err());
+    }
+
+    #[test]
+    fn test_raw() {
+        let config = Config {
+            raw: true,
+            escape_control: true,
+            ..Config::default()
+        };
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            name: String,
+            tags: Vec<String>,
+            attrs: BTreeMap<String, String>,
+        }
+
+        let test = Test {
+            name: "a\tb\u{1}".to_owned(),
+            tags: vec!["x".to_owned(), "y z".to_owned()],
+            attrs: BTreeMap::from([("k".to_owned(), "v".to_owned())]),
+        };
+        let expected = "a\tb\u{1}:x,y z:k=v";
+        let output = record_to_string_with_config(&test, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert!(!output.contains('\\'));
+        assert_eq!(
+            record_from_str_with_config::<Test>(&output, config.clone()).unwrap(),
+            test
+        );
+
+        // Backslashes are ordinary characters
+        let v = (r"C\dir\".to_owned(), "n".to_owned());
+        let expected = r"C\dir\,n";
+        let output = record_to_string_with_config(&v, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(
+            record_from_str_with_config::<(String, String)>(&output, config.clone()).unwrap(),
+            v
+        );
+
+        // A value containing a delimiter isn't read back the same
+        let v = ("a:b".to_owned(), "c".to_owned());
+        let output = record_to_string_with_config(&v, config.clone()).unwrap();
+        assert_eq!(output, "a:b,c");
+        assert!(record_from_str_with_config::<(String, String)>(&output, config.clone()).is_err());
+
+        let config = Config {
+            wrap_width: Some(80),
+            ..config
+        };
+        assert!(matches!(
+            record_to_string_with_config(&v, config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_escape_char() {
+        let config = Config {
+            escape_char: '~',
+            escape_control: true,
+            ..Config::default()
+        };
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            path: String,
+            list: Vec<String>,
+        }
+
+        let test = Test {
+            path: "C:\\a~b\n\u{1}".to_owned(),
+            list: vec!["x,y".to_owned(), "z:".to_owned()],
+        };
+        let expected = r"C~:\a~~b~n~x01:x~,y,z~:";
+        let output = record_to_string_with_config(&test, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(
+            record_from_str_with_config::<Test>(expected, config.clone()).unwrap(),
+            test
+        );
+
+        // A backslash is an ordinary character, and an escaped newline still
+        // continues the record
+        let v = "a\\,b~\nc";
+        let expected = ("a\\".to_owned(), "bc".to_owned());
+        assert_eq!(
+            record_from_str_with_config::<(String, String)>(v, config.clone()).unwrap(),
+            expected
+        );
+
+        let config = Config {
+            strict_escapes: true,
+            ..config
+        };
+        let err = record_from_str_with_config::<String>("a~q", config).unwrap_err();
+        assert_eq!("Unknown escape sequence `~q`", err.to_string());
+    }
+
+    #[test]
+    fn test_wrap_width() {
+        let config = Config {
+            wrap_width: Some(8),
+            escape_control: true,
+            ..Config::default()
+        };
+
+        let v = "abcdefghijklmnop";
+        let expected = "abcdefg\\\nhijklmn\\\nop";
+        let output = record_to_string_with_config(&v, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(
+            record_from_str_with_config::<String>(&output, config.clone()).unwrap(),
+            v
+        );
+
+        // Escape sequences aren't split, and numbers aren't wrapped
+        let v = (123456789u32, "a:b:c:\u{1}d".to_owned());
+        let expected = "123456789,\\\na\\:b\\:c\\\n\\:\\x01d";
+        let output = record_to_string_with_config(&v, config.clone()).unwrap();
+        assert_eq!(output, expected);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            LongVariantName(String),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            id: u32,
+            tuple: (u32, String),
+            list: Vec<String>,
+            map: BTreeMap<String, String>,
+            e: E,
+            ch: char,
+        }
+        let test = Test {
+            id: 1,
+            tuple: v,
+            list: vec!["a,b,c".to_owned(), "defghijkl".to_owned()],
+            map: BTreeMap::from([("key=1".to_owned(), "value=2".to_owned())]),
+            e: E::LongVariantName("x".to_owned()),
+            ch: 'c',
+        };
+        let output = record_to_string_with_config(&test, config.clone()).unwrap();
+        assert!(output.lines().count() > 1);
+        assert_eq!(
+            record_from_str_with_config::<Test>(&output, config.clone()).unwrap(),
+            test
+        );
+        for record in records_from_str::<Test>(&output) {
+            assert_eq!(record.unwrap(), test);
+        }
+    }
+
+    #[test]
+    fn test_spacing() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            txt: String,
+            seq: Vec<u32>,
+            opt: Option<u32>,
+            map: BTreeMap<String, String>,
+        }
+
+        let config = Config {
+            spacing: " ".to_owned(),
+            ..Config::default()
+        };
+
+        let test = Test {
+            txt: " a".to_owned(),
+            seq: vec![1, 2],
+            opt: None,
+            map: BTreeMap::from([("b".to_owned(), " c".to_owned())]),
+        };
+        // Only one space is skipped, so values may start with a space
+        let expected = " a: 1, 2: : b=  c";
+        assert_eq!(
+            record_to_string_with_config(&test, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Test>(expected, config).unwrap(),
+            test
+        );
+
+        let config = Config {
+            spacing: " :".to_owned(),
+            ..Config::default()
+        };
+        assert!(matches!(
+            record_to_string_with_config(&test, config),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_multibyte_delimiters() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            Tuple(u32, String),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            txt: String,
+            e: E,
+            map: BTreeMap<String, String>,
+        }
+
+        let config = Config {
+            field_delimiter: '§',
+            item_delimiter: '¦',
+            key_value_delimiter: '→',
+            ..Config::default()
+        };
+
+        let test = Test {
+            txt: "a§b".to_owned(),
+            e: E::Tuple(1, "c¦d".to_owned()),
+            map: BTreeMap::from([("e".to_owned(), "f→g".to_owned())]),
+        };
+        let expected = r"a\§b§Tuple§1¦c\¦d§e→f\→g";
+        assert_eq!(
+            record_to_string_with_config(&test, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Test>(expected, config).unwrap(),
+            test
+        );
+    }
+
+    #[test]
+    fn test_byte_seq() {
+        // Without `serde_bytes` a `Vec<u8>` is a list of numbers, not bytes
+        let v: Vec<u8> = vec![0, 255, 16];
+        let expected = "0,255,16";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<u8>>(expected).unwrap(), v);
+
+        assert!(matches!(
+            record_from_str::<Vec<u8>>("256"),
+            Err(Error::IntegerOverflow)
+        ));
+    }
+
+    #[cfg(all(feature = "hex", not(feature = "base64")))]
+    #[test]
+    fn test_bytes() {
+        let v = serde_bytes::Bytes::new(&[0, 255, 16]);
+        let expected = "00ff10";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<serde_bytes::ByteBuf>(expected).unwrap(),
+            v.as_ref()
+        );
+
+        let v = serde_bytes::Bytes::new(&[]);
+        assert_eq!(record_to_string(&v).unwrap(), "");
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn test_bytes() {
+        let v = serde_bytes::Bytes::new(&[0, 255, 16]);
+        let expected = "AP8Q";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<serde_bytes::ByteBuf>(expected).unwrap(),
+            v.as_ref()
+        );
+
+        // Padding is escaped inside a map
+        let v = BTreeMap::from([("a", serde_bytes::Bytes::new(&[0]))]);
+        let expected = r"a=AA\=\=";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<BTreeMap<String, serde_bytes::ByteBuf>>(expected).unwrap()["a"],
+            [0].as_ref()
+        );
+    }
+
+    #[cfg(not(any(feature = "hex", feature = "base64")))]
+    #[test]
+    fn test_bytes() {
+        let v = serde_bytes::Bytes::new(&[0, 255, 16]);
+        assert!(matches!(record_to_string(&v), Err(Error::BytesUnsupported)));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        let v = Utc.with_ymd_and_hms(2023, 8, 1, 12, 34, 56).unwrap();
+        let expected = r"2023-08-01T12\:34\:56Z";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<DateTime<Utc>>(expected).unwrap(), v);
+
+        // A different field delimiter leaves the colons unescaped
+        let config = Config {
+            field_delimiter: '|',
+            ..Config::default()
+        };
+        let expected = "2023-08-01T12:34:56Z";
+        assert_eq!(
+            record_to_string_with_config(&v, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<DateTime<Utc>>(expected, config).unwrap(),
+            v
+        );
+    }
+
+    #[test]
+    fn test_escape_control() {
+        let v = "\u{0}a\u{7}\u{7f}\n";
+
+        // Control characters are left as is by default
+        assert_eq!(record_to_string(&v).unwrap(), "\u{0}a\u{7}\u{7f}\\n");
+
+        let config = Config {
+            escape_control: true,
+            ..Config::default()
+        };
+        let expected = r"\x00a\x07\x7f\n";
+        assert_eq!(record_to_string_with_config(&v, config).unwrap(), expected);
+        assert_eq!(record_from_str::<String>(expected).unwrap(), v);
+    }
+
+    #[test]
+    fn test_null_token() {
+        let config = Config {
+            null_token: Some(r"\0".to_owned()),
+            ..Config::default()
+        };
+
+        let cases = [
+            (None, r"\0"),
+            (Some(String::new()), ""),
+            (Some("a".to_owned()), "a"),
+            (Some(r"\0".to_owned()), r"\\0"),
+        ];
+        for (v, expected) in cases {
+            let output = record_to_string_with_config(&v, config.clone()).unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(
+                record_from_str_with_config::<Option<String>>(&output, config.clone()).unwrap(),
+                v
+            );
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: Option<String>,
+            b: Option<Vec<u32>>,
+            c: Vec<Option<String>>,
+            d: BTreeMap<String, Option<u32>>,
+        }
+
+        let test = Test {
+            a: Some(String::new()),
+            b: None,
+            c: vec![None, Some(String::new()), Some("x".to_owned())],
+            d: BTreeMap::from([("k".to_owned(), None), ("l".to_owned(), Some(1))]),
+        };
+        let expected = r":\0:\0,,x:k=\0,l=1";
+        let output = record_to_string_with_config(&test, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(
+            record_from_str_with_config::<Test>(&output, config.clone()).unwrap(),
+            test
+        );
+
+        let test = Test {
+            a: None,
+            b: Some(vec![]),
+            c: vec![],
+            d: BTreeMap::new(),
+        };
+        let expected = r"\0:::";
+        let output = record_to_string_with_config(&test, config.clone()).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(
+            record_from_str_with_config::<Test>(&output, config).unwrap(),
+            test
+        );
+    }
+
+    #[test]
+    fn test_flags_reset() {
+        #[derive(Serialize)]
+        enum E {
+            Tuple(u32, (u32, u32)),
+            Struct { a: Option<u32> },
+        }
+
+        #[derive(Serialize)]
+        struct Inner {
+            pair: (String, u32),
+        }
+
+        #[derive(Serialize)]
+        struct Test {
+            list: Vec<(u32, Inner)>,
+            map: BTreeMap<String, Option<u32>>,
+            e: Vec<E>,
+            opt: Option<Vec<u32>>,
+            inner: Inner,
+        }
+
+        let test = Test {
+            list: vec![(
+                1,
+                Inner {
+                    pair: ("a".to_owned(), 2),
+                },
+            )],
+            map: BTreeMap::from([("k".to_owned(), Some(1)), ("l".to_owned(), None)]),
+            e: vec![E::Tuple(1, (2, 3)), E::Struct { a: Some(1) }],
+            opt: Some(vec![1, 2]),
+            inner: Inner {
+                pair: ("b".to_owned(), 3),
+            },
+        };
+
+        let mut ser = Serializer::new();
+        ser.serialize_record(&test).unwrap();
+        assert!(ser.is_reset());
+        ser.serialize_record(&Some(test.inner)).unwrap();
+        assert!(ser.is_reset());
+
+        // An error part way through leaves them set, until the next record
+        assert!(ser.serialize_record(&vec![vec![1]]).is_err());
+        ser.serialize_record(&"a").unwrap();
+        assert!(ser.is_reset());
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut buf = Vec::new();
+        record_to_writer(&mut buf, &("a", 1)).unwrap();
+        assert_eq!(buf, b"a,1");
+
+        struct Failing;
+
+        impl io::Write for Failing {
+            fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let err = record_to_writer(Failing, &"a").unwrap_err();
+        assert!(matches!(&err, Error::Io(io_err) if io_err.to_string() == "disk full"));
+        assert_eq!("IO error: disk full", err.to_string());
+        assert!(std::error::Error::source(&err).is_some());
+
+        // Values are written as they are serialized, not once the record is
+        struct Chunks(Vec<String>);
+
+        impl io::Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(String::from_utf8(buf.to_vec()).unwrap());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut chunks = Chunks(Vec::new());
+        record_to_writer(&mut chunks, &("a", 1)).unwrap();
+        assert_eq!(chunks.0, ["a", ",", "1"]);
+
+        let mut ser = Serializer::from_writer(Vec::new(), Config::default()).unwrap();
+        ser.write_record(&("a", 1)).unwrap();
+        // A failed record is left partly written
+        assert!(ser.write_record(&("c", vec![vec![1]])).is_err());
+        ser.write_record(&"b").unwrap();
+        assert_eq!(ser.into_inner(), b"a,1\nc,b\n");
+    }
+
+    #[test]
+    fn test_vec() {
+        let v = ("a:b", "\u{e9}");
+        assert_eq!(
+            record_to_vec(&v).unwrap(),
+            record_to_string(&v).unwrap().into_bytes()
+        );
+        assert_eq!(record_to_vec(&v).unwrap(), "a\\:b,\u{e9}".as_bytes());
+
+        let v = vec![vec![1]];
+        assert!(matches!(record_to_vec(&v), Err(Error::NestedCollection)));
+    }
+
+    #[test]
+    fn test_serialized_len() {
+        let v = ("a:b", "\u{e9}", 123);
+        assert_eq!(
+            serialized_len(&v).unwrap(),
+            record_to_string(&v).unwrap().len()
+        );
+        assert_eq!(serialized_len(&v).unwrap(), 11);
+        assert_eq!(serialized_len(&"").unwrap(), 0);
+
+        #[derive(Serialize)]
+        struct Test {
+            list: Vec<Option<u32>>,
+            map: BTreeMap<String, String>,
+            txt: String,
+        }
+
+        let test = Test {
+            list: vec![None, Some(1)],
+            map: BTreeMap::from([("k,1".to_owned(), "v=2".to_owned())]),
+            txt: "long text \u{e9}\n".repeat(4),
+        };
+        for config in [
+            Config::default(),
+            Config {
+                wrap_width: Some(10),
+                spacing: " ".to_owned(),
+                ..Config::default()
+            },
+        ] {
+            assert_eq!(
+                serialized_len_with_config(&test, config.clone()).unwrap(),
+                record_to_string_with_config(&test, config).unwrap().len()
+            );
+        }
+
+        // Map keys are still checked
+        let v = BTreeMap::from([((1, 2), 3)]);
+        assert!(matches!(serialized_len(&v), Err(Error::InvalidMapKey)));
+
+        let v = vec![vec![1]];
+        assert!(matches!(serialized_len(&v), Err(Error::NestedCollection)));
+    }
+
+    #[test]
+    fn test_serialize_record() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            int: u32,
+            txt: String,
+        }
+
+        let tests = [
+            Test {
+                int: 1,
+                txt: "a".to_owned(),
+            },
+            Test {
+                int: 2,
+                txt: "b\nc".to_owned(),
+            },
+            Test {
+                int: 3,
+                txt: "d:e".to_owned(),
+            },
+        ];
+
+        let mut ser = Serializer::new();
+        for test in &tests {
+            ser.serialize_record(test).unwrap();
+        }
+        // Nothing is appended for a record that fails
+        let nested = vec![vec![1]];
+        assert!(ser.serialize_record(&nested).is_err());
+
+        let output = ser.into_string();
+        assert_eq!(output, "1:a\n2:b\\nc\n3:d\\:e\n");
+        let records: Vec<Test> = records_from_str(&output).collect::<Result<_, _>>().unwrap();
+        assert_eq!(records, tests);
+        // Lines are still wrapped from where the failed record started
+        let config = Config {
+            wrap_width: Some(4),
+            ..Config::default()
+        };
+        let mut ser = Serializer::with_config(config).unwrap();
+        ser.serialize_record(&"abc").unwrap();
+        assert!(ser.serialize_record(&("abc", nested)).is_err());
+        ser.serialize_record(&"abcdef").unwrap();
+        assert_eq!(ser.into_string(), "abc\nabc\\\ndef\n");
+    }
+
+    #[test]
+    fn test_bool_as_int() {
+        let v = vec![true, false];
+        assert_eq!(record_to_string(&v).unwrap(), "true,false");
+
+        let config = Config {
+            bool_as_int: true,
+            ..Config::default()
+        };
+        let expected = "1,0";
+        assert_eq!(
+            record_to_string_with_config(&v, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Vec<bool>>(expected, config.clone()).unwrap(),
+            v
+        );
+        // Either form is read
+        assert_eq!(
+            record_from_str_with_config::<Vec<bool>>("true,false", config.clone()).unwrap(),
+            v
+        );
+
+        assert!(matches!(
+            record_from_str::<bool>("1"),
+            Err(Error::ExpectedBoolean)
+        ));
+
+        // Only a single digit is a boolean, and the same text reads as an integer
+        assert!(matches!(
+            record_from_str_with_config::<bool>("10", config.clone()),
+            Err(Error::ExpectedBoolean)
+        ));
+        assert_eq!(record_from_str_with_config::<u32>("1", config).unwrap(), 1);
     }
 
     #[test]
@@ -434,6 +2073,79 @@ b";
         let v = vec!["a=c", "b"];
         let expected = r#"a=c,b"#;
         assert_eq!(record_to_string(&v).unwrap(), expected);
+
+        let v: Vec<String> = vec![];
+        let expected = "";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<String>>(expected).unwrap(), v);
+
+        // An empty last item can't be told apart from a stray comma, or for a
+        // single item from an empty list
+        let v = vec!["a", ""];
+        assert!(matches!(record_to_string(&v), Err(Error::EmptyLastItem)));
+        let v = vec![Some(1), None];
+        assert!(matches!(record_to_string(&v), Err(Error::EmptyLastItem)));
+        let v = vec![""];
+        assert!(matches!(record_to_string(&v), Err(Error::EmptyLastItem)));
+
+        // A trailing delimiter tells them apart
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+        let v = vec!["a", ""];
+        let expected = "a,,";
+        assert_eq!(
+            record_to_string_with_config(&v, config.clone()).unwrap(),
+            expected
+        );
+        assert_eq!(
+            record_from_str_with_config::<Vec<String>>(expected, config).unwrap(),
+            v
+        );
+    }
+
+    #[test]
+    fn test_sets() {
+        let v = BTreeSet::from(["b".to_owned(), "a,c".to_owned()]);
+        let expected = r"a\,c,b";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<BTreeSet<String>>(expected).unwrap(), v);
+
+        let v = HashSet::from([3u32, 1, 2]);
+        let output = record_to_string(&v).unwrap();
+        let mut items: Vec<&str> = output.split(',').collect();
+        items.sort();
+        assert_eq!(items, ["1", "2", "3"]);
+        assert_eq!(record_from_str::<HashSet<u32>>(&output).unwrap(), v);
+
+        // Repeated items are merged
+        let v = "1,2,1";
+        assert_eq!(
+            record_from_str::<HashSet<u32>>(v).unwrap(),
+            HashSet::from([1, 2])
+        );
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            id: u32,
+            tags: BTreeSet<String>,
+        }
+        let test = Test {
+            id: 1,
+            tags: BTreeSet::from(["x".to_owned(), "y".to_owned()]),
+        };
+        let expected = "1:x,y";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        let test = Test {
+            id: 1,
+            tags: BTreeSet::new(),
+        };
+        let expected = "1:";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
     }
 
     #[test]
@@ -441,6 +2153,149 @@ b";
         let v = ("a", "b");
         let expected = r#"a,b"#;
         assert_eq!(record_to_string(&v).unwrap(), expected);
+
+        let v = [1u32, 2, 3];
+        let expected = "1,2,3";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+
+        let v = (1u32, "a,b".to_owned(), true);
+        let expected = r"1,a\,b,true";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<(u32, String, bool)>(expected).unwrap(), v);
+
+        // A tuple is a list, so may not hold a list or map
+        let v = (1u32, vec!["a".to_owned()], true);
+        assert!(matches!(record_to_string(&v), Err(Error::NestedCollection)));
+        assert!(matches!(
+            record_from_str::<(u32, Vec<String>, bool)>("1,a,true"),
+            Err(Error::NestedCollection)
+        ));
+    }
+
+    #[test]
+    fn test_seq_of_tuples() {
+        let v = vec![("a,b", "c"), ("d", "e,f")];
+        let expected = r#"a\,b,c,d,e\,f"#;
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+
+        // The outer tuple keeps escaping after the inner one ends
+        let v = (("a", "b"), "c,d");
+        let expected = r#"a,b,c\,d"#;
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_smart_pointers() {
+        let v = Box::new(vec!["a,b".to_owned(), "c".to_owned()]);
+        let expected = r"a\,b,c";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Box<Vec<String>>>(expected).unwrap(), v);
+
+        let v = Arc::new("a:b".to_owned());
+        let expected = r"a\:b";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Arc<String>>(expected).unwrap(), v);
+
+        // Flags are left as they were after each pointer
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            seq: Rc<Vec<Arc<str>>>,
+            txt: Box<str>,
+        }
+
+        let test = Test {
+            seq: Rc::new(vec![Arc::from("a,b")]),
+            txt: Box::from("c,d"),
+        };
+        let expected = r"a\,b:c,d";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_unit() {
+        // Unit is written as nothing, so a list of them always ends with an
+        // empty item
+        for v in [vec![()], vec![(), (), ()]] {
+            assert!(matches!(record_to_string(&v), Err(Error::EmptyLastItem)));
+        }
+        assert!(matches!(
+            record_from_str::<Vec<()>>(","),
+            Err(Error::ExpectedArrayEnd)
+        ));
+
+        // Unless every item is followed by a delimiter
+        let config = Config {
+            trailing_delimiter: true,
+            ..Config::default()
+        };
+        for (v, expected) in [(vec![()], ","), (vec![(), (), ()], ",,,")] {
+            assert_eq!(
+                record_to_string_with_config(&v, config.clone()).unwrap(),
+                expected
+            );
+            assert_eq!(
+                record_from_str_with_config::<Vec<()>>(expected, config.clone()).unwrap(),
+                v
+            );
+        }
+
+        let v: Vec<()> = vec![];
+        assert_eq!(record_to_string(&v).unwrap(), "");
+        assert_eq!(record_from_str::<Vec<()>>("").unwrap(), v);
+
+        // A tuple has a fixed length, so may end with an empty item
+        let v = (1, (), None::<u32>);
+        let expected = "1,,";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<(u32, (), Option<u32>)>(expected).unwrap(),
+            v
+        );
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: (),
+            b: u32,
+            c: (),
+        }
+
+        let test = Test { a: (), b: 1, c: () };
+        let expected = ":1:";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_nested_seq() {
+        let v: Vec<Vec<u32>> = vec![vec![1, 2], vec![3]];
+        assert!(matches!(record_to_string(&v), Err(Error::NestedCollection)));
+        assert!(matches!(
+            record_from_str::<Vec<Vec<u32>>>("1,2,3"),
+            Err(Error::NestedCollection)
+        ));
+
+        let v = vec![BTreeMap::from([("a", 1)])];
+        assert!(matches!(record_to_string(&v), Err(Error::NestedCollection)));
+        assert!(matches!(
+            record_from_str::<Vec<BTreeMap<String, u32>>>("a=1"),
+            Err(Error::NestedCollection)
+        ));
+
+        // Separate fields of a struct may each hold a list
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: Vec<u32>,
+            b: Vec<u32>,
+        }
+
+        let test = Test {
+            a: vec![1, 2],
+            b: vec![3],
+        };
+        let expected = "1,2:3";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
     }
 
     #[test]
@@ -454,6 +2309,144 @@ b";
         assert_eq!(record_to_string(&v).unwrap(), expected);
     }
 
+    #[test]
+    fn test_nested_option() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            value: Option<Option<String>>,
+            id: u32,
+        }
+
+        let cases = [(None, ":1"), (Some(Some("a".to_owned())), "a:1")];
+        for (value, expected) in cases {
+            let test = Test { value, id: 1 };
+            assert_eq!(record_to_string(&test).unwrap(), expected);
+            assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+        }
+
+        // Only `Some(None)` looks the same as `None`
+        let test = Test {
+            value: Some(None),
+            id: 1,
+        };
+        let err = record_to_string(&test).unwrap_err();
+        assert!(matches!(err, Error::NestedOption));
+
+        // Unless `None` is written as the null token
+        let config = Config {
+            null_token: Some(r"\0".to_owned()),
+            ..Config::default()
+        };
+        let cases = [
+            (None, r"\0:1"),
+            (Some(None), ":1"),
+            (Some(Some("a".to_owned())), "a:1"),
+        ];
+        for (value, expected) in cases {
+            let test = Test { value, id: 1 };
+            let output = record_to_string_with_config(&test, config.clone()).unwrap();
+            assert_eq!(output, expected);
+            assert_eq!(
+                record_from_str_with_config::<Test>(expected, config.clone()).unwrap(),
+                test
+            );
+        }
+        let v: Option<Option<Option<u32>>> = Some(Some(None));
+        let err = record_to_string_with_config(&v, config.clone()).unwrap_err();
+        assert!(matches!(err, Error::NestedOption));
+
+        // Only the value of the `Some` itself is nested
+        let v = Some(vec![Some(String::new()), None]);
+        let output = record_to_string_with_config(&v, config.clone()).unwrap();
+        assert_eq!(output, r",\0");
+        assert_eq!(
+            record_from_str_with_config::<Option<Vec<Option<String>>>>(&output, config).unwrap(),
+            v
+        );
+
+        // Options inside a list or struct inside an option are fine
+        let v = Some(vec![Some(1), None, Some(2)]);
+        assert_eq!(record_to_string(&v).unwrap(), "1,,2");
+        let v = Some(Test { value: None, id: 1 });
+        assert_eq!(record_to_string(&v).unwrap(), ":1");
+
+        // An enum can hold all three states
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum TriState {
+            Unset,
+            Null,
+            Value(String),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tagged {
+            value: TriState,
+            id: u32,
+        }
+
+        let cases = [
+            (TriState::Unset, "Unset:1"),
+            (TriState::Null, "Null:1"),
+            (TriState::Value("a".to_owned()), "Value:a:1"),
+        ];
+        for (value, expected) in cases {
+            let test = Tagged { value, id: 1 };
+            assert_eq!(record_to_string(&test).unwrap(), expected);
+            assert_eq!(record_from_str::<Tagged>(expected).unwrap(), test);
+        }
+    }
+
+    #[test]
+    fn test_option_seq() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            list: Option<Vec<String>>,
+            id: u32,
+        }
+
+        let test = Test {
+            list: Some(vec!["a".to_owned()]),
+            id: 1,
+        };
+        let expected = "a:1";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        // A missing list and an empty list are both written as an empty field,
+        // and read back as `None`
+        let expected = ":1";
+        for list in [None, Some(vec![])] {
+            let test = Test { list, id: 1 };
+            assert_eq!(record_to_string(&test).unwrap(), expected);
+        }
+        let test = Test { list: None, id: 1 };
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        // An enum can tell them apart, at the cost of a field for the variant
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum MaybeList {
+            Missing,
+            Present(Vec<String>),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Tagged {
+            list: MaybeList,
+            id: u32,
+        }
+
+        let cases = [
+            (MaybeList::Missing, "Missing:1"),
+            (MaybeList::Present(vec![]), "Present::1"),
+            (MaybeList::Present(vec!["a".to_owned()]), "Present:a:1"),
+        ];
+        for (list, expected) in cases {
+            let test = Tagged { list, id: 1 };
+            assert_eq!(record_to_string(&test).unwrap(), expected);
+            assert_eq!(record_from_str::<Tagged>(expected).unwrap(), test);
+        }
+    }
+
     #[test]
     fn test_map() {
         let mut map = std::collections::HashMap::new();
@@ -480,6 +2473,75 @@ b";
         map.insert("a", "b=c");
         let expected = r#"a=b\=c"#;
         assert_eq!(record_to_string(&map).unwrap(), expected);
+
+        // non-string keys
+        let map = BTreeMap::from([(1, "a"), (20, "b")]);
+        let expected = r#"1=a,20=b"#;
+        assert_eq!(record_to_string(&map).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_invalid_map_key() {
+        let v = HashMap::from([(vec!["a".to_owned(), "b".to_owned()], 1)]);
+        let err = record_to_string(&v).unwrap_err();
+        assert!(matches!(err, Error::InvalidMapKey));
+        assert_eq!(
+            "Map keys must be a single value, not a list, tuple, or struct",
+            err.to_string()
+        );
+
+        let v = BTreeMap::from([((1, 2), 3)]);
+        assert!(matches!(record_to_string(&v), Err(Error::InvalidMapKey)));
+
+        #[derive(Serialize, PartialEq, Eq, PartialOrd, Ord)]
+        struct Key {
+            a: u32,
+            b: u32,
+        }
+        let v = BTreeMap::from([(Key { a: 1, b: 2 }, 3)]);
+        assert!(matches!(record_to_string(&v), Err(Error::InvalidMapKey)));
+
+        // Escaped delimiters in a string key are fine
+        let v = BTreeMap::from([("a,b=c:d", 1)]);
+        let expected = r"a\,b\=c\:d=1";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_empty_map() {
+        let map: HashMap<String, String> = HashMap::new();
+        let expected = "";
+        assert_eq!(record_to_string(&map).unwrap(), expected);
+        assert_eq!(
+            record_from_str::<HashMap<String, String>>(expected).unwrap(),
+            map
+        );
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            a: HashMap<String, String>,
+            b: u32,
+            c: HashMap<String, String>,
+        }
+
+        let test = Test {
+            a: HashMap::new(),
+            b: 1,
+            c: HashMap::new(),
+        };
+        let expected = ":1:";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_btree_map_order() {
+        // Entries are written in the order the map iterates them
+        let map = BTreeMap::from([("c", "3"), ("a", "1"), ("b", "2")]);
+        let expected = r#"a=1,b=2,c=3"#;
+        for _ in 0..10 {
+            assert_eq!(record_to_string(&map).unwrap(), expected);
+        }
     }
 
     #[test]
@@ -507,6 +2569,106 @@ b";
         assert_eq!(record_to_string(&test).unwrap(), expected);
     }
 
+    #[test]
+    fn test_skipped_field() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            opt: Option<u32>,
+            #[serde(skip_serializing_if = "String::is_empty")]
+            txt: String,
+            int: u32,
+        }
+
+        let test = Test {
+            opt: None,
+            txt: "".to_owned(),
+            int: 1,
+        };
+        let expected = "::1";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        let test = Test {
+            opt: Some(2),
+            txt: "a".to_owned(),
+            int: 1,
+        };
+        let expected = "2:a:1";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_flatten() {
+        #[derive(Serialize)]
+        struct Test {
+            id: &'static str,
+            #[serde(flatten)]
+            extra: BTreeMap<&'static str, &'static str>,
+        }
+
+        let test = Test {
+            id: "1",
+            extra: BTreeMap::from([("a", "b"), ("c", "d")]),
+        };
+        let expected = r#"id=1,a=b,c=d"#;
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_tagged_enum() {
+        #[derive(Serialize)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            Newtype(u32),
+            Tuple(u32, u32),
+        }
+
+        let n = Adjacent::Newtype(1);
+        assert_eq!(record_to_string(&n).unwrap(), "Newtype:1");
+        let t = Adjacent::Tuple(1, 2);
+        assert_eq!(record_to_string(&t).unwrap(), "Tuple:1,2");
+
+        #[derive(Serialize)]
+        #[serde(tag = "t")]
+        enum Internal {
+            Struct { a: &'static str, b: &'static str },
+        }
+
+        let s = Internal::Struct { a: "x", b: "y" };
+        assert_eq!(record_to_string(&s).unwrap(), "Struct:x:y");
+    }
+
+    #[test]
+    fn test_duration() {
+        let d = Duration::new(5, 500);
+        let expected = "5:500";
+        assert_eq!(record_to_string(&d).unwrap(), expected);
+        assert_eq!(record_from_str::<Duration>(expected).unwrap(), d);
+
+        let t = UNIX_EPOCH + d;
+        assert_eq!(record_to_string(&t).unwrap(), expected);
+        assert_eq!(record_from_str::<SystemTime>(expected).unwrap(), t);
+
+        // The two fields are flattened into the surrounding struct
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            name: String,
+            timeout: Duration,
+            retries: u32,
+        }
+
+        let test = Test {
+            name: "a".to_owned(),
+            timeout: d,
+            retries: 3,
+        };
+        let expected = "a:5:500:3";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
     #[test]
     fn test_enum() {
         #[derive(Serialize)]
@@ -533,4 +2695,217 @@ b";
         let expected = r#"Struct:1"#;
         assert_eq!(record_to_string(&s).unwrap(), expected);
     }
+
+    #[test]
+    fn test_rename() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(rename_all = "snake_case")]
+        enum E {
+            UnitVariant,
+            #[serde(rename = "foo")]
+            Newtype(u32),
+            #[serde(alias = "s")]
+            StructVariant {
+                #[serde(rename = "b")]
+                a: u32,
+            },
+        }
+
+        // Field names aren't written, so renaming them changes nothing
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(rename_all = "UPPERCASE")]
+        struct Test {
+            #[serde(rename = "x")]
+            e: E,
+            id: u32,
+        }
+
+        let e = E::UnitVariant;
+        let expected = "unit_variant";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+
+        let e = E::Newtype(1);
+        let expected = "foo:1";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+        assert!(record_from_str::<E>("Newtype:1").is_err());
+
+        let e = E::StructVariant { a: 1 };
+        let expected = "struct_variant:1";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+        assert_eq!(record_from_str::<E>("s:1").unwrap(), e);
+
+        let test = Test {
+            e: E::Newtype(1),
+            id: 2,
+        };
+        let expected = "foo:1:2";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_newtype_variant_seq() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            List(Vec<u32>),
+        }
+
+        let e = E::List(vec![1, 2, 3]);
+        let expected = "List:1,2,3";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+
+        let e = E::List(vec![]);
+        let expected = "List:";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            e: E,
+            id: u32,
+        }
+
+        let test = Test {
+            e: E::List(vec![1, 2]),
+            id: 3,
+        };
+        let expected = "List:1,2:3";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+    }
+
+    #[test]
+    fn test_seq_of_structs() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Simple {
+            id: u32,
+            name: String,
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            items: Vec<Simple>,
+            n: u32,
+        }
+
+        // Structs in a list are flattened like tuples
+        let test = Test {
+            items: vec![
+                Simple {
+                    id: 1,
+                    name: "a:b".to_owned(),
+                },
+                Simple {
+                    id: 2,
+                    name: "c,d".to_owned(),
+                },
+            ],
+            n: 3,
+        };
+        let expected = r"1,a\:b,2,c\,d:3";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        let err = record_from_str::<Vec<Simple>>("1,a,2").unwrap_err();
+        assert_eq!(
+            "invalid length 1, expected struct Simple with 2 elements",
+            err.to_string()
+        );
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            S { id: u32, name: String },
+        }
+        let v = vec![
+            E::S {
+                id: 1,
+                name: "a".to_owned(),
+            },
+            E::S {
+                id: 2,
+                name: "b".to_owned(),
+            },
+        ];
+        let expected = "S:1,a,S:2,b";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<E>>(expected).unwrap(), v);
+
+        // A struct can't be a map value, as its fields look like more entries
+        let v = BTreeMap::from([(
+            "a",
+            Simple {
+                id: 1,
+                name: "b".to_owned(),
+            },
+        )]);
+        assert!(matches!(record_to_string(&v), Err(Error::NestedCollection)));
+        assert!(matches!(
+            record_from_str::<BTreeMap<String, Simple>>("a=1,b"),
+            Err(Error::NestedCollection)
+        ));
+    }
+
+    #[test]
+    fn test_struct_variant_seq() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            Struct { items: Vec<u32> },
+            StructV { items: Vec<String>, n: u32 },
+        }
+
+        let e = E::Struct {
+            items: vec![1, 2, 3],
+        };
+        let expected = "Struct:1,2,3";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+
+        let e = E::StructV {
+            items: vec!["a".to_owned(), "b:c".to_owned()],
+            n: 2,
+        };
+        let expected = r"StructV:a,b\:c:2";
+        assert_eq!(record_to_string(&e).unwrap(), expected);
+        assert_eq!(record_from_str::<E>(expected).unwrap(), e);
+    }
+
+    #[test]
+    fn test_tuple_variant_field() {
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        enum E {
+            Tuple(u32, String),
+        }
+
+        #[derive(Serialize, Deserialize, PartialEq, Debug)]
+        struct Test {
+            name: String,
+            e: E,
+            id: u32,
+        }
+
+        let test = Test {
+            name: "a".to_owned(),
+            e: E::Tuple(1, "b,c:d".to_owned()),
+            id: 2,
+        };
+        let expected = r"a:Tuple:1,b\,c\:d:2";
+        assert_eq!(record_to_string(&test).unwrap(), expected);
+        assert_eq!(record_from_str::<Test>(expected).unwrap(), test);
+
+        // Too few items stop at the end of the field
+        let err = record_from_str::<Test>("a:Tuple:1:2").unwrap_err();
+        assert_eq!(
+            "invalid length 1, expected tuple variant E::Tuple with 2 elements",
+            err.to_string()
+        );
+
+        let v = vec![E::Tuple(1, "a".to_owned()), E::Tuple(2, "b".to_owned())];
+        let expected = "Tuple:1,a,Tuple:2,b";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(record_from_str::<Vec<E>>(expected).unwrap(), v);
+    }
 }
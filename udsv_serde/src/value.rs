@@ -0,0 +1,246 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{self, Deserialize, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+/// Any UDSV value, for working with records without a concrete type.
+///
+/// UDSV isn't self-describing, so deserializing is best effort: a record with
+/// several fields is a `Seq` of them, a field containing `=` is a `Map`, one
+/// containing `,` is a `Seq`, and anything else is a scalar guessed from its
+/// text, with the empty string as `Null`.
+///
+/// As the guess only sees the text, a `String` that looks like another scalar
+/// doesn't round-trip, e.g. `Value::String("true")` is read back as
+/// `Value::Bool(true)` and `Value::String("1")` as `Value::Int(1)`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    /// An integer too large for `Int`.
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Seq(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    fn from_text(s: &str) -> Value {
+        if s.is_empty() {
+            return Value::Null;
+        }
+        if let Ok(b) = s.parse() {
+            return Value::Bool(b);
+        }
+        if let Ok(i) = s.parse() {
+            return Value::Int(i);
+        }
+        if let Ok(u) = s.parse() {
+            return Value::UInt(u);
+        }
+        // `str::parse` also accepts words like `inf` and `nan`, only treat
+        // text that looks like a number as a float.
+        if s.bytes().any(|b| b.is_ascii_digit()) {
+            if let Ok(f) = s.parse() {
+                return Value::Float(f);
+            }
+        }
+        Value::String(s.to_owned())
+    }
+}
+
+// A `Seq` or `Map` containing another `Seq` or `Map` can't be written, as
+// nested lists and maps aren't supported.
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::UInt(u) => serializer.serialize_u64(*u),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Seq(vec) => serializer.collect_seq(vec),
+            Value::Map(map) => serializer.collect_map(map),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any UDSV value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(i64::try_from(v).map_or(Value::UInt(v), Value::Int))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::from_text(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut vec = Vec::new();
+        while let Some(v) = seq.next_element()? {
+            vec.push(v);
+        }
+        Ok(Value::Seq(vec))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = BTreeMap::new();
+        while let Some((k, v)) = map.next_entry()? {
+            values.insert(k, v);
+        }
+        Ok(Value::Map(values))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::BTreeMap;
+
+    use crate::{record_from_str, record_to_string, Error, Value};
+
+    #[test]
+    fn test_deserialize() {
+        let v = "a";
+        assert_eq!(Value::String("a".to_owned()), record_from_str(v).unwrap());
+
+        let v = "";
+        assert_eq!(Value::Null, record_from_str(v).unwrap());
+
+        let v = "true";
+        assert_eq!(Value::Bool(true), record_from_str(v).unwrap());
+
+        let v = "-1";
+        assert_eq!(Value::Int(-1), record_from_str(v).unwrap());
+
+        let v = "1.5";
+        assert_eq!(Value::Float(1.5), record_from_str(v).unwrap());
+
+        let v = u64::MAX.to_string();
+        assert_eq!(Value::UInt(u64::MAX), record_from_str(&v).unwrap());
+        let v = i64::MAX.to_string();
+        assert_eq!(Value::Int(i64::MAX), record_from_str(&v).unwrap());
+
+        // Words that `str::parse` reads as floats stay strings
+        let v = "nan";
+        assert_eq!(Value::String("nan".to_owned()), record_from_str(v).unwrap());
+
+        let v = r"a:1,b\,c:k=v,j=";
+        let expected = Value::Seq(vec![
+            Value::String("a".to_owned()),
+            Value::Seq(vec![Value::Int(1), Value::String("b,c".to_owned())]),
+            Value::Map(BTreeMap::from([
+                ("j".to_owned(), Value::Null),
+                ("k".to_owned(), Value::String("v".to_owned())),
+            ])),
+        ]);
+        assert_eq!(expected, record_from_str(v).unwrap());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let v = Value::Seq(vec![
+            Value::Int(1),
+            Value::Float(1.0),
+            Value::Null,
+            Value::Bool(true),
+            Value::String("a,b".to_owned()),
+        ]);
+        let expected = r"1,1.0,,true,a\,b";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(v, record_from_str(expected).unwrap());
+
+        let v = Value::Map(BTreeMap::from([
+            ("a".to_owned(), Value::Int(-1)),
+            ("b".to_owned(), Value::String("x=y".to_owned())),
+        ]));
+        let expected = r"a=-1,b=x\=y";
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(v, record_from_str(expected).unwrap());
+
+        let v = Value::UInt(u64::MAX);
+        let expected = u64::MAX.to_string();
+        assert_eq!(record_to_string(&v).unwrap(), expected);
+        assert_eq!(v, record_from_str(&expected).unwrap());
+
+        let v = Value::Seq(vec![Value::Seq(vec![])]);
+        assert!(matches!(record_to_string(&v), Err(Error::NestedCollection)));
+    }
+
+    #[test]
+    fn test_guessed_strings() {
+        // Strings that look like another scalar are read back as that scalar
+        let cases = [
+            (Value::String("true".to_owned()), Value::Bool(true)),
+            (Value::String("1".to_owned()), Value::Int(1)),
+            (Value::String("1.5".to_owned()), Value::Float(1.5)),
+            (Value::String(String::new()), Value::Null),
+        ];
+        for (v, expected) in cases {
+            let output = record_to_string(&v).unwrap();
+            assert_eq!(expected, record_from_str(&output).unwrap());
+        }
+
+        // Any other text is kept
+        let v = Value::String("1a".to_owned());
+        assert_eq!(v, record_from_str(&record_to_string(&v).unwrap()).unwrap());
+    }
+}